@@ -1,6 +1,7 @@
 use crate::launcher::auth::provider::Credentials;
-use crate::launcher::rules::parse_rules;
-use crate::launcher::LauncherError;
+use crate::launcher::options::{LaunchOptions, QuickPlay};
+use crate::launcher::rules::{parse_argument_rules, parse_rules, RuleContext};
+use crate::launcher::{LauncherError, Side};
 use daedalus::get_path_from_artifact;
 use daedalus::minecraft::{Argument, ArgumentValue, Library, Os, VersionType};
 use daedalus::modded::SidedDataEntry;
@@ -13,12 +14,47 @@ pub fn get_class_paths(
     libraries_path: &Path,
     libraries: &[Library],
     client_path: &Path,
+    rule_context: &RuleContext,
 ) -> Result<String, LauncherError> {
+    let mut class_paths = get_library_paths(libraries_path, libraries, rule_context)?;
+
+    class_paths.push(
+        crate::util::absolute_path(&client_path)
+            .map_err(|_| {
+                LauncherError::InvalidInput(format!(
+                    "Specified class path {} does not exist",
+                    client_path.to_string_lossy()
+                ))
+            })?
+            .to_string_lossy()
+            .to_string(),
+    );
+
+    Ok(class_paths.join(get_class_path_separator()))
+}
+
+/// Gets the class path of the libraries alone, for loaders which find the game jar themselves
+pub fn get_library_class_paths(
+    libraries_path: &Path,
+    libraries: &[Library],
+    rule_context: &RuleContext,
+) -> Result<String, LauncherError> {
+    Ok(
+        get_library_paths(libraries_path, libraries, rule_context)?
+            .join(get_class_path_separator()),
+    )
+}
+
+fn get_library_paths(
+    libraries_path: &Path,
+    libraries: &[Library],
+    rule_context: &RuleContext,
+) -> Result<Vec<String>, LauncherError> {
     let mut class_paths = Vec::new();
 
     for library in libraries {
         if let Some(rules) = &library.rules {
-            if !super::rules::parse_rules(rules.as_slice()) {
+            if !parse_rules(rules.as_slice(), rule_context) {
                 continue;
             }
         }
@@ -30,22 +66,14 @@ pub fn get_class_paths(
         class_paths.push(get_lib_path(libraries_path, &library.name)?);
     }
 
-    class_paths.push(
-        crate::util::absolute_path(&client_path)
-            .map_err(|_| {
-                LauncherError::InvalidInput(format!(
-                    "Specified class path {} does not exist",
-                    client_path.to_string_lossy()
-                ))
-            })?
-            .to_string_lossy()
-            .to_string(),
-    );
+    Ok(class_paths)
+}
 
-    Ok(class_paths.join(match super::download::get_os() {
+fn get_class_path_separator() -> &'static str {
+    match super::platform::get_os() {
         Os::Osx | Os::Linux | Os::Unknown => ":",
         Os::Windows => ";",
-    }))
+    }
 }
 
 pub fn get_class_paths_jar<T: AsRef<str>>(
@@ -58,10 +86,7 @@ pub fn get_class_paths_jar<T: AsRef<str>>(
         class_paths.push(get_lib_path(libraries_path, library)?)
     }
 
-    Ok(class_paths.join(match super::download::get_os() {
-        Os::Osx | Os::Linux | Os::Unknown => ":",
-        Os::Windows => ";",
-    }))
+    Ok(class_paths.join(get_class_path_separator()))
 }
 
 pub fn get_lib_path<T: AsRef<str>>(libraries_path: &Path, lib: T) -> Result<String, LauncherError> {
@@ -90,14 +115,20 @@ pub fn get_lib_path<T: AsRef<str>>(libraries_path: &Path, lib: T) -> Result<Stri
 pub fn get_jvm_arguments(
     arguments: Option<&[Argument]>,
     natives_path: &Path,
+    libraries_path: &Path,
     class_paths: &str,
+    version_name: &str,
+    options: &LaunchOptions,
 ) -> Result<Vec<String>, LauncherError> {
-    let mut parsed_arguments = Vec::new();
+    let mut parsed_arguments = get_custom_jvm_arguments(options);
 
     if let Some(args) = arguments {
-        parse_arguments(args, &mut parsed_arguments, |arg| {
-            parse_jvm_argument(arg, natives_path, class_paths)
-        })?;
+        parse_arguments(
+            args,
+            &mut parsed_arguments,
+            &options.get_rule_context(),
+            |arg| parse_jvm_argument(arg, natives_path, libraries_path, class_paths, version_name),
+        )?;
     } else {
         parsed_arguments.push(format!(
             "-Djava.library.path={}",
@@ -116,10 +147,27 @@ pub fn get_jvm_arguments(
     Ok(parsed_arguments)
 }
 
+/// Gets the JVM arguments set by the launch options, such as the heap size
+pub fn get_custom_jvm_arguments(options: &LaunchOptions) -> Vec<String> {
+    let mut arguments = Vec::new();
+
+    if let Some(memory) = options.max_memory {
+        arguments.push(format!("-Xmx{}M", memory));
+    }
+    if let Some(memory) = options.min_memory {
+        arguments.push(format!("-Xms{}M", memory));
+    }
+    arguments.extend(options.jvm_arguments.iter().cloned());
+
+    arguments
+}
+
 fn parse_jvm_argument(
     argument: &str,
     natives_path: &Path,
+    libraries_path: &Path,
     class_paths: &str,
+    version_name: &str,
 ) -> Result<String, LauncherError> {
     let mut argument = argument.to_string();
     argument.retain(|c| !c.is_whitespace());
@@ -136,11 +184,52 @@ fn parse_jvm_argument(
                 .to_string_lossy()
                 .to_string(),
         )
+        .replace(
+            "${library_directory}",
+            &*crate::util::absolute_path(libraries_path)?.to_string_lossy(),
+        )
+        .replace("${classpath_separator}", get_class_path_separator())
+        .replace("${version_name}", version_name)
         .replace("${launcher_name}", "theseus")
         .replace("${launcher_version}", env!("CARGO_PKG_VERSION"))
         .replace("${classpath}", class_paths))
 }
 
+/// Gets the game arguments a mod loader starts its server with, which are the arguments of its
+/// client from its launch target onwards with the launch target of the server, e.g.
+/// `--launchTarget forgeserver --fml.forgeVersion 40.1.0`. Returns `None` if the loader has no
+/// launch target
+pub fn get_server_loader_arguments(arguments: Option<&[Argument]>) -> Option<Vec<String>> {
+    let arguments = arguments?
+        .iter()
+        .filter_map(|x| match x {
+            Argument::Normal(arg) => Some(arg.clone()),
+            Argument::Ruled { .. } => None,
+        })
+        .skip_while(|x| x != "--launchTarget")
+        .collect::<Vec<_>>();
+
+    let target = arguments.get(1)?.strip_suffix("client")?;
+
+    let mut server_arguments = arguments.clone();
+    server_arguments[1] = format!("{}server", target);
+
+    Some(server_arguments)
+}
+
+/// Gets the main class of a Forge server started through the legacy launch wrapper, which sits
+/// next to the tweaker of the client, e.g. `net.minecraftforge.fml.relauncher.ServerLaunchWrapper`
+pub fn get_legacy_server_main_class(legacy_arguments: Option<&str>) -> Option<String> {
+    let mut arguments = legacy_arguments?.split(' ');
+    arguments.find(|x| *x == "--tweakClass")?;
+
+    let package = arguments
+        .next()?
+        .strip_suffix(".common.launcher.FMLTweaker")?;
+
+    Some(format!("{}.relauncher.ServerLaunchWrapper", package))
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn get_minecraft_arguments(
     arguments: Option<&[Argument]>,
@@ -151,13 +240,34 @@ pub fn get_minecraft_arguments(
     game_directory: &Path,
     assets_directory: &Path,
     version_type: &VersionType,
+    options: &LaunchOptions,
 ) -> Result<Vec<String>, LauncherError> {
-    if let Some(arguments) = arguments {
-        let mut parsed_arguments = Vec::new();
+    let mut parsed_arguments = Vec::new();
 
-        parse_arguments(arguments, &mut parsed_arguments, |arg| {
+    if let Some(arguments) = arguments {
+        parse_arguments(
+            arguments,
+            &mut parsed_arguments,
+            &options.get_rule_context(),
+            |arg| {
+                parse_minecraft_argument(
+                    arg,
+                    &*credentials.access_token,
+                    &*credentials.username,
+                    &credentials.id,
+                    version,
+                    asset_index_name,
+                    game_directory,
+                    assets_directory,
+                    version_type,
+                    options,
+                )
+            },
+        )?;
+    } else if let Some(legacy_arguments) = legacy_arguments {
+        parsed_arguments.extend(
             parse_minecraft_argument(
-                arg,
+                legacy_arguments,
                 &*credentials.access_token,
                 &*credentials.username,
                 &credentials.id,
@@ -166,29 +276,28 @@ pub fn get_minecraft_arguments(
                 game_directory,
                 assets_directory,
                 version_type,
-            )
-        })?;
+                options,
+            )?
+            .split(' ')
+            .into_iter()
+            .map(|x| x.to_string()),
+        );
+
+        // Legacy versions have no feature rules adding the resolution arguments
+        if let Some((width, height)) = options.resolution {
+            parsed_arguments.push("--width".to_string());
+            parsed_arguments.push(width.to_string());
+            parsed_arguments.push("--height".to_string());
+            parsed_arguments.push(height.to_string());
+        }
+    }
 
-        Ok(parsed_arguments)
-    } else if let Some(legacy_arguments) = legacy_arguments {
-        Ok(parse_minecraft_argument(
-            legacy_arguments,
-            &*credentials.access_token,
-            &*credentials.username,
-            &credentials.id,
-            version,
-            asset_index_name,
-            game_directory,
-            assets_directory,
-            version_type,
-        )?
-        .split(' ')
-        .into_iter()
-        .map(|x| x.to_string())
-        .collect())
-    } else {
-        Ok(Vec::new())
+    if options.fullscreen {
+        parsed_arguments.push("--fullscreen".to_string());
     }
+    parsed_arguments.extend(options.game_arguments.iter().cloned());
+
+    Ok(parsed_arguments)
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -202,7 +311,16 @@ fn parse_minecraft_argument(
     game_directory: &Path,
     assets_directory: &Path,
     version_type: &VersionType,
+    options: &LaunchOptions,
 ) -> Result<String, LauncherError> {
+    let (width, height) = options.resolution.unwrap_or_default();
+    let (singleplayer, multiplayer, realms) = match &options.quick_play {
+        Some(QuickPlay::Singleplayer(world)) => (world.as_str(), "", ""),
+        Some(QuickPlay::Multiplayer(server)) => ("", server.as_str(), ""),
+        Some(QuickPlay::Realms(realm)) => ("", "", realm.as_str()),
+        None => ("", "", ""),
+    };
+
     Ok(argument
         .replace("${auth_access_token}", access_token)
         .replace("${auth_session}", access_token)
@@ -248,12 +366,18 @@ fn parse_minecraft_argument(
                 .to_string_lossy()
                 .to_string(),
         )
-        .replace("${version_type}", version_type.as_str()))
+        .replace("${version_type}", version_type.as_str())
+        .replace("${resolution_width}", &width.to_string())
+        .replace("${resolution_height}", &height.to_string())
+        .replace("${quickPlaySingleplayer}", singleplayer)
+        .replace("${quickPlayMultiplayer}", multiplayer)
+        .replace("${quickPlayRealms}", realms))
 }
 
 fn parse_arguments<F>(
     arguments: &[Argument],
     parsed_arguments: &mut Vec<String>,
+    rule_context: &RuleContext,
     parse_function: F,
 ) -> Result<(), LauncherError>
 where
@@ -269,16 +393,14 @@ where
                 }
             }
             Argument::Ruled { rules, value } => {
-                if parse_rules(rules.as_slice()) {
-                    match value {
-                        ArgumentValue::Single(arg) => {
-                            parsed_arguments.push(parse_function(arg)?);
-                        }
-                        ArgumentValue::Many(args) => {
-                            for arg in args {
-                                parsed_arguments.push(parse_function(arg)?);
-                            }
-                        }
+                let values = match value {
+                    ArgumentValue::Single(arg) => std::slice::from_ref(arg),
+                    ArgumentValue::Many(args) => args.as_slice(),
+                };
+
+                if parse_argument_rules(rules.as_slice(), values, rule_context) {
+                    for arg in values {
+                        parsed_arguments.push(parse_function(arg)?);
                     }
                 }
             }
@@ -292,29 +414,58 @@ pub fn get_processor_arguments<T: AsRef<str>>(
     libraries_path: &Path,
     arguments: &[T],
     data: &HashMap<String, SidedDataEntry>,
+    side: Side,
 ) -> Result<Vec<String>, LauncherError> {
     let mut new_arguments = Vec::new();
 
     for argument in arguments {
-        let trimmed_arg = &argument.as_ref()[1..argument.as_ref().len() - 1];
-        if argument.as_ref().starts_with('{') {
-            if let Some(entry) = data.get(trimmed_arg) {
-                new_arguments.push(if entry.client.starts_with('[') {
-                    get_lib_path(libraries_path, &entry.client[1..entry.client.len() - 1])?
-                } else {
-                    entry.client.clone()
-                })
-            }
-        } else if argument.as_ref().starts_with('[') {
-            new_arguments.push(get_lib_path(libraries_path, trimmed_arg)?)
-        } else {
-            new_arguments.push(argument.as_ref().to_string())
+        if let Some(value) = get_processor_value(libraries_path, argument.as_ref(), data, side)? {
+            new_arguments.push(value);
         }
     }
 
     Ok(new_arguments)
 }
 
+/// Resolves an argument or output of a processor. `{KEY}` is replaced with the data of the side,
+/// `[artifact]` with the path of a library and `'literal'` with its contents. Returns `None` for
+/// data keys which don't exist
+pub fn get_processor_value(
+    libraries_path: &Path,
+    value: &str,
+    data: &HashMap<String, SidedDataEntry>,
+    side: Side,
+) -> Result<Option<String>, LauncherError> {
+    if let Some(key) = strip_delimiters(value, '{', '}') {
+        return match data.get(key) {
+            Some(entry) => Ok(Some(resolve_processor_literal(
+                libraries_path,
+                match side {
+                    Side::Client => &entry.client,
+                    Side::Server => &entry.server,
+                },
+            )?)),
+            None => Ok(None),
+        };
+    }
+
+    Ok(Some(resolve_processor_literal(libraries_path, value)?))
+}
+
+fn resolve_processor_literal(libraries_path: &Path, value: &str) -> Result<String, LauncherError> {
+    if let Some(artifact) = strip_delimiters(value, '[', ']') {
+        get_lib_path(libraries_path, artifact)
+    } else if let Some(literal) = strip_delimiters(value, '\'', '\'') {
+        Ok(literal.to_string())
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+fn strip_delimiters(value: &str, start: char, end: char) -> Option<&str> {
+    value.strip_prefix(start)?.strip_suffix(end)
+}
+
 pub async fn get_processor_main_class(path: String) -> Result<Option<String>, LauncherError> {
     Ok(tokio::task::spawn_blocking(move || {
         let zipfile = std::fs::File::open(&path)?;
@@ -343,3 +494,150 @@ pub async fn get_processor_main_class(path: String) -> Result<Option<String>, La
     })
     .await??)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> HashMap<String, SidedDataEntry> {
+        let mut data = HashMap::new();
+        data.insert(
+            "MAPPINGS".to_string(),
+            SidedDataEntry {
+                client: "[de.oceanlabs.mcp:mcp_config:1.18.2:mappings@txt]".to_string(),
+                server: "[de.oceanlabs.mcp:mcp_config:1.18.2:server-mappings@txt]".to_string(),
+            },
+        );
+        data.insert(
+            "MC_HASH".to_string(),
+            SidedDataEntry {
+                client: "'abcdef'".to_string(),
+                server: "'012345'".to_string(),
+            },
+        );
+        data
+    }
+
+    fn lib_path(path: &str) -> String {
+        crate::util::absolute_path(Path::new("libraries").join(path))
+            .unwrap()
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn processor_data_keys_resolve_per_side() {
+        let libraries = Path::new("libraries");
+
+        assert_eq!(
+            get_processor_value(libraries, "{MC_HASH}", &data(), Side::Client).unwrap(),
+            Some("abcdef".to_string())
+        );
+        assert_eq!(
+            get_processor_value(libraries, "{MC_HASH}", &data(), Side::Server).unwrap(),
+            Some("012345".to_string())
+        );
+        assert_eq!(
+            get_processor_value(libraries, "{MAPPINGS}", &data(), Side::Server).unwrap(),
+            Some(lib_path(
+                "de/oceanlabs/mcp/mcp_config/1.18.2/mcp_config-1.18.2-server-mappings.txt"
+            ))
+        );
+        assert_eq!(
+            get_processor_value(libraries, "{MISSING}", &data(), Side::Client).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn processor_literals_and_artifacts_resolve() {
+        let libraries = Path::new("libraries");
+
+        assert_eq!(
+            get_processor_value(libraries, "'--side'", &data(), Side::Client).unwrap(),
+            Some("--side".to_string())
+        );
+        assert_eq!(
+            get_processor_value(
+                libraries,
+                "[net.minecraftforge:forge:1.18.2-40.1.0:client]",
+                &data(),
+                Side::Client
+            )
+            .unwrap(),
+            Some(lib_path(
+                "net/minecraftforge/forge/1.18.2-40.1.0/forge-1.18.2-40.1.0-client.jar"
+            ))
+        );
+        assert_eq!(
+            get_processor_value(libraries, "--input", &data(), Side::Client).unwrap(),
+            Some("--input".to_string())
+        );
+    }
+
+    fn normal(arguments: &[&str]) -> Vec<Argument> {
+        arguments
+            .iter()
+            .map(|x| Argument::Normal(x.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn server_loader_arguments_target_the_server() {
+        let arguments = normal(&[
+            "--username",
+            "${auth_player_name}",
+            "--launchTarget",
+            "forgeclient",
+            "--fml.forgeVersion",
+            "40.1.0",
+        ]);
+
+        assert_eq!(
+            get_server_loader_arguments(Some(&arguments)),
+            Some(vec![
+                "--launchTarget".to_string(),
+                "forgeserver".to_string(),
+                "--fml.forgeVersion".to_string(),
+                "40.1.0".to_string(),
+            ])
+        );
+        assert_eq!(
+            get_server_loader_arguments(Some(&normal(&["--launchTarget", "fmlclient"]))),
+            Some(vec!["--launchTarget".to_string(), "fmlserver".to_string()])
+        );
+    }
+
+    #[test]
+    fn server_loader_arguments_need_a_client_launch_target() {
+        assert_eq!(
+            get_server_loader_arguments(Some(&normal(&["--username", "x"]))),
+            None
+        );
+        assert_eq!(
+            get_server_loader_arguments(Some(&normal(&["--launchTarget"]))),
+            None
+        );
+        assert_eq!(get_server_loader_arguments(None), None);
+    }
+
+    #[test]
+    fn legacy_server_main_class_sits_next_to_the_tweaker() {
+        assert_eq!(
+            get_legacy_server_main_class(Some(
+                "--username ${auth_player_name} --tweakClass net.minecraftforge.fml.common.launcher.FMLTweaker"
+            )),
+            Some("net.minecraftforge.fml.relauncher.ServerLaunchWrapper".to_string())
+        );
+        assert_eq!(
+            get_legacy_server_main_class(Some(
+                "--tweakClass cpw.mods.fml.common.launcher.FMLTweaker --username x"
+            )),
+            Some("cpw.mods.fml.relauncher.ServerLaunchWrapper".to_string())
+        );
+        assert_eq!(
+            get_legacy_server_main_class(Some("--username ${auth_player_name}")),
+            None
+        );
+    }
+}
@@ -32,12 +32,11 @@ pub mod api {
     }
 
     pub async fn login(
+        client: &reqwest::Client,
         username: &str,
         password: &str,
         request_user: bool,
     ) -> Result<AuthenticateResponse, reqwest::Error> {
-        let client = reqwest::Client::new();
-
         client
             .post("https://authserver.mojang.com/authenticate")
             .header(reqwest::header::CONTENT_TYPE, "application/json")
@@ -62,9 +61,11 @@ pub mod api {
             .await
     }
 
-    pub async fn sign_out(username: &str, password: &str) -> Result<(), reqwest::Error> {
-        let client = reqwest::Client::new();
-
+    pub async fn sign_out(
+        client: &reqwest::Client,
+        username: &str,
+        password: &str,
+    ) -> Result<(), reqwest::Error> {
         client
             .post("https://authserver.mojang.com/signout")
             .header(reqwest::header::CONTENT_TYPE, "application/json")
@@ -83,9 +84,11 @@ pub mod api {
         Ok(())
     }
 
-    pub async fn validate(access_token: &str, client_token: &str) -> Result<(), reqwest::Error> {
-        let client = reqwest::Client::new();
-
+    pub async fn validate(
+        client: &reqwest::Client,
+        access_token: &str,
+        client_token: &str,
+    ) -> Result<(), reqwest::Error> {
         client
             .post("https://authserver.mojang.com/validate")
             .header(reqwest::header::CONTENT_TYPE, "application/json")
@@ -104,9 +107,11 @@ pub mod api {
         Ok(())
     }
 
-    pub async fn invalidate(access_token: &str, client_token: &str) -> Result<(), reqwest::Error> {
-        let client = reqwest::Client::new();
-
+    pub async fn invalidate(
+        client: &reqwest::Client,
+        access_token: &str,
+        client_token: &str,
+    ) -> Result<(), reqwest::Error> {
         client
             .post("https://authserver.mojang.com/invalidate")
             .header(reqwest::header::CONTENT_TYPE, "application/json")
@@ -135,13 +140,12 @@ pub mod api {
     }
 
     pub async fn refresh(
+        client: &reqwest::Client,
         access_token: &str,
         client_token: &str,
         selected_profile: &GameProfile,
         request_user: bool,
     ) -> Result<RefreshResponse, reqwest::Error> {
-        let client = reqwest::Client::new();
-
         client
             .post("https://authserver.mojang.com/refresh")
             .header(reqwest::header::CONTENT_TYPE, "application/json")
@@ -184,14 +188,17 @@ pub mod provider {
 
     impl Credentials {
         /// Gets a credentials instance from a user's login
-        pub async fn from_login(username: &str, password: &str) -> Result<Self, LauncherError> {
-            let login =
-                login(username, password, true)
-                    .await
-                    .map_err(|err| LauncherError::FetchError {
-                        inner: err,
-                        item: "authentication credentials".to_string(),
-                    })?;
+        pub async fn from_login(
+            client: &reqwest::Client,
+            username: &str,
+            password: &str,
+        ) -> Result<Self, LauncherError> {
+            let login = login(client, username, password, true)
+                .await
+                .map_err(|err| LauncherError::FetchError {
+                    inner: err,
+                    item: "authentication credentials".to_string(),
+                })?;
 
             let profile = login.selected_profile.unwrap();
 
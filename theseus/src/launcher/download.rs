@@ -1,19 +1,148 @@
+use crate::launcher::http::HttpSettings;
+use crate::launcher::mirrors::{Mirrors, ASSETS_URL, LIBRARIES_URL};
+use crate::launcher::platform::Platform;
+use crate::launcher::progress::{DownloadPhase, PhaseProgress, ProgressReporter};
+use crate::launcher::retry::{retry_fetch, AttemptError, RetryPolicy};
+use crate::launcher::rules::{parse_rules, RuleContext};
 use crate::launcher::LauncherError;
 use daedalus::get_path_from_artifact;
 use daedalus::minecraft::{
-    fetch_assets_index, fetch_version_info, Asset, AssetsIndex, DownloadType, Library, Os, Version,
-    VersionInfo,
+    Asset, AssetsIndex, DownloadType, Library, LibraryDownload, Version, VersionInfo,
 };
-use daedalus::modded::{fetch_partial_version, merge_partial_version, LoaderVersion};
+use daedalus::modded::{merge_partial_version, LoaderVersion, PartialVersionInfo};
 use futures::future;
+use lazy_static::lazy_static;
+use sha2::Digest;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::Write;
-use std::path::Path;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+lazy_static! {
+    /// Bounds how many files are hashed at the same time. Checking an installation hashes every
+    /// file it has, each on a thread of the blocking pool
+    static ref HASH_LIMITER: Semaphore = Semaphore::new(
+        std::thread::available_parallelism()
+            .map(|x| x.get())
+            .unwrap_or(4)
+    );
+}
+
+/// The default amount of files downloaded at the same time
+pub const DEFAULT_CONCURRENT_DOWNLOADS: usize = 32;
+
+#[derive(Debug, Clone)]
+/// Settings and state shared by every download of an installation
+pub struct DownloadContext {
+    limiter: Arc<Semaphore>,
+    metadata_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    /// Locks of the paths being downloaded to, so downloads sharing a destination (such as assets
+    /// with the same hash) don't write to the same partial file at once
+    path_locks: Arc<std::sync::Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>>,
+    /// The HTTP client all files are downloaded with
+    pub client: reqwest::Client,
+    /// How failed downloads are retried
+    pub retry: RetryPolicy,
+    /// The mirrors files are downloaded from
+    pub mirrors: Mirrors,
+    /// The reporter download progress is sent to
+    pub progress: ProgressReporter,
+}
+
+impl DownloadContext {
+    /// Creates a context which downloads at most `max_concurrent` files at the same time
+    /// using the given client. Manifests and stalled downloads time out after the default
+    /// timeouts of [`HttpSettings`]
+    pub fn new(client: reqwest::Client, max_concurrent: usize) -> Self {
+        DownloadContext {
+            limiter: Arc::new(Semaphore::new(max_concurrent.max(1))),
+            metadata_timeout: HttpSettings::default().get_metadata_timeout(),
+            read_timeout: HttpSettings::default().read_timeout,
+            path_locks: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            client,
+            retry: RetryPolicy::default(),
+            mirrors: Mirrors::default(),
+            progress: ProgressReporter::default(),
+        }
+    }
+
+    /// Creates a context which downloads at most `max_concurrent` files at the same time
+    /// using a client built from the given settings
+    pub fn from_settings(
+        settings: &HttpSettings,
+        max_concurrent: usize,
+    ) -> Result<Self, LauncherError> {
+        let mut context = DownloadContext::new(settings.build()?, max_concurrent);
+        context.metadata_timeout = settings.get_metadata_timeout();
+        context.read_timeout = settings.read_timeout;
+
+        Ok(context)
+    }
+
+    /// How long a single request for a manifest may take
+    pub(crate) fn metadata_timeout(&self) -> Option<Duration> {
+        self.metadata_timeout
+    }
+
+    /// Sets how failed downloads are retried
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Sets the mirrors files are downloaded from
+    pub fn mirrors(mut self, mirrors: Mirrors) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    /// Sets the reporter download progress is sent to
+    pub fn progress(mut self, progress: ProgressReporter) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    fn get_path_lock(&self, path: &Path) -> Arc<tokio::sync::Mutex<()>> {
+        self.path_locks
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .entry(path.to_path_buf())
+            .or_default()
+            .clone()
+    }
+
+    /// Forgets the lock of a path once nothing is waiting on it anymore
+    fn release_path_lock(&self, path: &Path, lock: Arc<tokio::sync::Mutex<()>>) {
+        drop(lock);
+
+        let mut locks = self
+            .path_locks
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        if locks.get(path).map(Arc::strong_count) == Some(1) {
+            locks.remove(path);
+        }
+    }
+}
+
+impl Default for DownloadContext {
+    fn default() -> Self {
+        DownloadContext::from_settings(&HttpSettings::default(), DEFAULT_CONCURRENT_DOWNLOADS)
+            .expect("Unable to build the default HTTP client")
+    }
+}
 
 pub async fn download_version_info(
     client_path: &Path,
     version: &Version,
     loader_version: Option<&LoaderVersion>,
+    context: &DownloadContext,
 ) -> Result<VersionInfo, LauncherError> {
     let id = loader_version.map(|x| &x.id).unwrap_or(&version.id);
 
@@ -22,10 +151,13 @@ pub async fn download_version_info(
     if path.exists() {
         Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
     } else {
-        let mut info = fetch_version_info(version).await?;
+        let mut info: VersionInfo = serde_json::from_slice(
+            &download_file(context, &version.url, Some(&version.sha1)).await?,
+        )?;
 
         if let Some(loader_version) = loader_version {
-            let partial = fetch_partial_version(&*loader_version.url).await?;
+            let partial: PartialVersionInfo =
+                serde_json::from_slice(&download_file(context, &loader_version.url, None).await?)?;
 
             info = merge_partial_version(partial, info);
 
@@ -41,22 +173,70 @@ pub async fn download_version_info(
 pub async fn download_client(
     client_path: &Path,
     version_info: &VersionInfo,
+    context: &DownloadContext,
 ) -> Result<(), LauncherError> {
-    let client_download = version_info
-        .downloads
-        .get(&DownloadType::Client)
-        .ok_or_else(|| {
-            LauncherError::InvalidInput(format!(
-                "Version {} does not have any client downloads",
-                &version_info.id
-            ))
-        })?;
-
-    let path = &*client_path
-        .join(&version_info.id)
-        .join(format!("{}.jar", &version_info.id));
+    download_game_jar(
+        &client_path
+            .join(&version_info.id)
+            .join(format!("{}.jar", &version_info.id)),
+        version_info,
+        DownloadType::Client,
+        DownloadPhase::Client,
+        context,
+    )
+    .await
+}
 
-    save_and_download_file(path, &client_download.url, Some(&client_download.sha1)).await?;
+pub async fn download_server(
+    versions_path: &Path,
+    version_info: &VersionInfo,
+    context: &DownloadContext,
+) -> Result<(), LauncherError> {
+    download_game_jar(
+        &get_server_path(versions_path, &version_info.id),
+        version_info,
+        DownloadType::Server,
+        DownloadPhase::Server,
+        context,
+    )
+    .await
+}
+
+/// Gets the path the dedicated server jar of a version is stored at
+pub fn get_server_path(versions_path: &Path, version_id: &str) -> PathBuf {
+    versions_path
+        .join(version_id)
+        .join(format!("{}-server.jar", version_id))
+}
+
+async fn download_game_jar(
+    path: &Path,
+    version_info: &VersionInfo,
+    download_type: DownloadType,
+    phase: DownloadPhase,
+    context: &DownloadContext,
+) -> Result<(), LauncherError> {
+    let download = version_info.downloads.get(&download_type).ok_or_else(|| {
+        LauncherError::InvalidInput(format!(
+            "Version {} does not have any {} downloads",
+            &version_info.id,
+            match download_type {
+                DownloadType::Server => "server",
+                _ => "client",
+            }
+        ))
+    })?;
+
+    let progress = context.progress.phase(phase, 1, download.size as u64);
+
+    save_and_download_file(
+        context,
+        path,
+        &context.mirrors.get_urls(&download.url),
+        Some(&download.sha1),
+    )
+    .await?;
+    progress.complete_file(download.size as u64);
 
     Ok(())
 }
@@ -64,32 +244,40 @@ pub async fn download_client(
 pub async fn download_assets_index(
     assets_path: &Path,
     version: &VersionInfo,
+    context: &DownloadContext,
 ) -> Result<AssetsIndex, LauncherError> {
     let path = &*assets_path
         .join("indexes")
         .join(format!("{}.json", &version.asset_index.id));
 
-    if path.exists() {
-        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
-    } else {
-        let index = fetch_assets_index(version).await?;
-
-        save_file(path, &bytes::Bytes::from(serde_json::to_string(&index)?))?;
+    save_and_download_file(
+        context,
+        path,
+        &context.mirrors.get_urls(&version.asset_index.url),
+        Some(&version.asset_index.sha1),
+    )
+    .await?;
 
-        Ok(index)
-    }
+    Ok(serde_json::from_slice(&std::fs::read(path)?)?)
 }
 
 pub async fn download_assets(
     assets_path: &Path,
     legacy_path: Option<&Path>,
     index: &AssetsIndex,
+    context: &DownloadContext,
 ) -> Result<(), LauncherError> {
+    let progress = context.progress.phase(
+        DownloadPhase::Assets,
+        index.objects.len() as u64,
+        index.objects.values().map(|x| x.size as u64).sum(),
+    );
+
     future::join_all(
         index
             .objects
             .iter()
-            .map(|x| download_asset(assets_path, legacy_path, x.0, x.1)),
+            .map(|x| download_asset(context, assets_path, legacy_path, x.0, x.1, &progress)),
     )
     .await
     .into_iter()
@@ -99,44 +287,95 @@ pub async fn download_assets(
 }
 
 async fn download_asset(
+    context: &DownloadContext,
     assets_path: &Path,
     legacy_path: Option<&Path>,
     name: &str,
     asset: &Asset,
+    progress: &PhaseProgress,
 ) -> Result<(), LauncherError> {
     let sub_hash = &&asset.hash[..2];
 
     let resource_path = assets_path.join("objects").join(sub_hash).join(&asset.hash);
 
-    let resource = save_and_download_file(
+    save_and_download_file(
+        context,
         &*resource_path,
-        &format!(
-            "https://resources.download.minecraft.net/{}/{}",
-            sub_hash, asset.hash
-        ),
+        &context
+            .mirrors
+            .get_urls(&format!("{}{}/{}", ASSETS_URL, sub_hash, asset.hash)),
         Some(&*asset.hash),
     )
     .await?;
 
     if let Some(legacy_path) = legacy_path {
-        let resource_path =
+        let legacy_resource_path =
             legacy_path.join(name.replace('/', &*std::path::MAIN_SEPARATOR.to_string()));
-        save_file(resource_path.as_path(), &resource)?;
+        copy_file(&resource_path, legacy_resource_path.as_path())?;
     }
 
+    progress.complete_file(asset.size as u64);
+
     Ok(())
 }
 
+/// Downloads the libraries of a version for a platform. Natives are only downloaded and extracted
+/// when a natives directory is given
 pub async fn download_libraries(
     libraries_path: &Path,
-    natives_path: &Path,
+    natives_path: Option<&Path>,
     libraries: &[Library],
+    platform: &Platform,
+    context: &DownloadContext,
 ) -> Result<(), LauncherError> {
-    future::join_all(
-        libraries
+    let rule_context = RuleContext::new(platform.clone());
+    let libraries = libraries
+        .iter()
+        .filter(|x| {
+            x.rules
+                .as_ref()
+                .map(|rules| parse_rules(rules.as_slice(), &rule_context))
+                .unwrap_or(true)
+        })
+        .collect::<Vec<_>>();
+
+    let jars = libraries
+        .iter()
+        .filter(|x| get_library_artifact(x).is_some() || x.downloads.is_none())
+        .collect::<Vec<_>>();
+    let natives = match natives_path {
+        Some(_) => libraries
             .iter()
-            .map(|x| download_library(libraries_path, natives_path, x)),
-    )
+            .filter_map(|x| get_native(x, platform))
+            .collect::<Vec<_>>(),
+        None => Vec::new(),
+    };
+
+    let library_progress = context.progress.phase(
+        DownloadPhase::Libraries,
+        jars.len() as u64,
+        jars.iter()
+            .filter_map(|x| get_library_artifact(x))
+            .map(|x| x.size as u64)
+            .sum(),
+    );
+    let native_progress = context.progress.phase(
+        DownloadPhase::Natives,
+        natives.len() as u64,
+        natives.iter().map(|x| x.size as u64).sum(),
+    );
+
+    future::join_all(libraries.iter().map(|x| {
+        download_library(
+            context,
+            libraries_path,
+            natives_path,
+            x,
+            platform,
+            &library_progress,
+            &native_progress,
+        )
+    }))
     .await
     .into_iter()
     .collect::<Result<Vec<()>, LauncherError>>()?;
@@ -145,19 +384,32 @@ pub async fn download_libraries(
 }
 
 async fn download_library(
+    context: &DownloadContext,
     libraries_path: &Path,
-    natives_path: &Path,
+    natives_path: Option<&Path>,
     library: &Library,
+    platform: &Platform,
+    library_progress: &PhaseProgress,
+    native_progress: &PhaseProgress,
 ) -> Result<(), LauncherError> {
-    if let Some(rules) = &library.rules {
-        if !super::rules::parse_rules(rules.as_slice()) {
-            return Ok(());
-        }
-    }
-
     let (a, b) = future::join(
-        download_library_jar(libraries_path, library),
-        download_native(natives_path, library),
+        download_library_jar(context, libraries_path, library, library_progress),
+        async {
+            match natives_path {
+                Some(natives_path) => {
+                    download_native(
+                        context,
+                        libraries_path,
+                        natives_path,
+                        library,
+                        platform,
+                        native_progress,
+                    )
+                    .await
+                }
+                None => Ok(()),
+            }
+        },
     )
     .await;
 
@@ -168,151 +420,678 @@ async fn download_library(
 }
 
 async fn download_library_jar(
+    context: &DownloadContext,
     libraries_path: &Path,
     library: &Library,
+    progress: &PhaseProgress,
 ) -> Result<(), LauncherError> {
     let mut path = libraries_path.to_path_buf();
     path.push(get_path_from_artifact(&*library.name)?);
 
     if let Some(downloads) = &library.downloads {
         if let Some(library) = &downloads.artifact {
-            save_and_download_file(&*path, &library.url, Some(&library.sha1)).await?;
+            save_and_download_file(
+                context,
+                &*path,
+                &context.mirrors.get_urls(&library.url),
+                Some(&library.sha1),
+            )
+            .await?;
+            progress.complete_file(library.size as u64);
         }
     } else {
         save_and_download_file(
+            context,
             &*path,
-            &format!(
-                "{}{}",
-                library
-                    .url
-                    .as_deref()
-                    .unwrap_or("https://libraries.minecraft.net/"),
-                get_path_from_artifact(&*library.name)?
+            &context.mirrors.get_maven_urls(
+                library.url.as_deref().unwrap_or(LIBRARIES_URL),
+                &get_path_from_artifact(&library.name)?,
             ),
             None,
         )
         .await?;
+        progress.complete_file(0);
+    }
+
+    Ok(())
+}
+
+fn get_library_artifact(library: &Library) -> Option<&LibraryDownload> {
+    library.downloads.as_ref().and_then(|x| x.artifact.as_ref())
+}
+
+async fn download_native(
+    context: &DownloadContext,
+    libraries_path: &Path,
+    natives_path: &Path,
+    library: &Library,
+    platform: &Platform,
+    progress: &PhaseProgress,
+) -> Result<(), LauncherError> {
+    if let Some(native) = get_native(library, platform) {
+        let path = libraries_path.join(&native.path);
+
+        save_and_download_file(
+            context,
+            &path,
+            &context.mirrors.get_urls(&native.url),
+            Some(&native.sha1),
+        )
+        .await?;
+
+        let natives_path = natives_path.to_path_buf();
+        let exclude = library
+            .extract
+            .as_ref()
+            .and_then(|x| x.exclude.clone())
+            .unwrap_or_default();
+        let sha1 = native.sha1.clone();
+
+        tokio::task::spawn_blocking(move || {
+            extract_native(&path, &natives_path, exclude.as_slice(), &sha1)
+        })
+        .await??;
+
+        progress.complete_file(native.size as u64);
     }
 
     Ok(())
 }
 
-async fn download_native(natives_path: &Path, library: &Library) -> Result<(), LauncherError> {
-    if let Some(natives) = &library.natives {
-        if let Some(os_key) = natives.get(&get_os()) {
-            if let Some(downloads) = &library.downloads {
-                if let Some(classifiers) = &downloads.classifiers {
-                    #[cfg(target_pointer_width = "64")]
-                    let parsed_key = os_key.replace("${arch}", "64");
-                    #[cfg(target_pointer_width = "32")]
-                    let parsed_key = os_key.replace("${arch}", "32");
+/// Extracts a native archive, skipping the entries starting with one of the library's excluded
+/// paths. A record of the extracted files is kept, so an archive is only extracted again when it
+/// changed or one of its files went missing
+fn extract_native(
+    archive_path: &Path,
+    natives_path: &Path,
+    exclude: &[String],
+    sha1: &str,
+) -> Result<(), LauncherError> {
+    let record_path = natives_path.join(".extracted").join(sha1);
+
+    if let Ok(record) = std::fs::read_to_string(&record_path) {
+        if record.lines().all(|x| natives_path.join(x).exists()) {
+            return Ok(());
+        }
+    }
 
-                    if let Some(native) = classifiers.get(&*parsed_key) {
-                        let file = download_file(&native.url, Some(&native.sha1)).await?;
+    let archive_error = |err| LauncherError::ArchiveError {
+        inner: err,
+        path: archive_path.to_string_lossy().to_string(),
+    };
 
-                        let reader = std::io::Cursor::new(&*file);
+    let mut archive = zip::ZipArchive::new(File::open(archive_path)?).map_err(archive_error)?;
+    let mut extracted = Vec::new();
 
-                        let mut archive = zip::ZipArchive::new(reader).unwrap();
-                        archive.extract(natives_path).unwrap();
-                    }
-                }
-            }
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).map_err(archive_error)?;
+
+        if file.is_dir() || exclude.iter().any(|x| file.name().starts_with(&**x)) {
+            continue;
+        }
+
+        // Entries which would be extracted outside of the natives directory are skipped
+        let name = match file.enclosed_name() {
+            Some(name) => name.to_path_buf(),
+            None => continue,
+        };
+
+        let out_path = natives_path.join(&name);
+
+        if let Some(parent) = out_path.parent() {
+            std::fs::create_dir_all(parent)?;
         }
+
+        std::io::copy(&mut file, &mut File::create(&out_path)?)?;
+
+        extracted.push(name.to_string_lossy().to_string());
     }
 
+    save_file(&record_path, &bytes::Bytes::from(extracted.join("\n")))?;
+
     Ok(())
 }
 
+/// Gets the natives of a library built for a platform, which doesn't need to be the one the
+/// launcher is running on
+fn get_native<'a>(library: &'a Library, platform: &Platform) -> Option<&'a LibraryDownload> {
+    let os_key = library.natives.as_ref()?.get(&platform.os)?;
+    let classifiers = library.downloads.as_ref()?.classifiers.as_ref()?;
+
+    let arch = platform.arch;
+    let parsed_key = os_key.replace("${arch}", arch.bits());
+
+    // Prefer natives built for the architecture specifically, falling back to the generic ones
+    arch.classifier_suffix()
+        .and_then(|suffix| classifiers.get(&format!("{}-{}", parsed_key, suffix)))
+        .or_else(|| classifiers.get(&parsed_key))
+}
+
 async fn save_and_download_file(
+    context: &DownloadContext,
     path: &Path,
-    url: &str,
+    urls: &[String],
     sha1: Option<&str>,
-) -> Result<bytes::Bytes, LauncherError> {
-    let read = std::fs::read(path).ok().map(bytes::Bytes::from);
+) -> Result<(), LauncherError> {
+    if path.exists() {
+        match sha1 {
+            Some(sha1) if hash_file(path).await?.sha1 != sha1 => {}
+            _ => return Ok(()),
+        }
+    }
 
-    if let Some(bytes) = read {
-        Ok(bytes)
-    } else {
-        let file = download_file(url, sha1).await?;
+    download_from_mirrors(context, urls, path, sha1).await?;
+
+    Ok(())
+}
+
+/// Downloads a file from the first of the given URLs which succeeds. If every URL fails, the
+/// errors of all of them are returned
+pub async fn download_from_mirrors(
+    context: &DownloadContext,
+    urls: &[String],
+    path: &Path,
+    sha1: Option<&str>,
+) -> Result<FileHashes, LauncherError> {
+    let mut errors = Vec::new();
+
+    for url in urls {
+        match download_to_file(context, url, path, sha1).await {
+            Ok(hashes) => return Ok(hashes),
+            Err(err) => errors.push(err),
+        }
+    }
+
+    match errors.len() {
+        0 => Err(LauncherError::InvalidInput(format!(
+            "No URLs to download {} from",
+            path.to_string_lossy()
+        ))),
+        1 => Err(errors.remove(0)),
+        _ => Err(LauncherError::MirrorsExhausted {
+            path: path.to_string_lossy().to_string(),
+            errors,
+        }),
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// Why a file of an installation failed verification
+pub enum FileStatus {
+    /// The file doesn't exist
+    Missing,
+    /// The SHA1 hash of the file doesn't match the expected one
+    Corrupt {
+        /// The hash the file should have
+        expected: String,
+        /// The hash the file has
+        actual: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+/// A file of an installation which is missing or corrupt
+pub struct InvalidFile {
+    /// The path of the file
+    pub path: PathBuf,
+    /// Why the file is invalid
+    pub status: FileStatus,
+}
+
+pub async fn verify_client(
+    client_path: &Path,
+    version_info: &VersionInfo,
+) -> Result<Vec<InvalidFile>, LauncherError> {
+    let mut invalid = Vec::new();
+
+    let path = client_path
+        .join(&version_info.id)
+        .join(format!("{}.jar", &version_info.id));
+
+    verify_file(
+        path,
+        version_info
+            .downloads
+            .get(&DownloadType::Client)
+            .map(|x| &*x.sha1),
+        &mut invalid,
+    )
+    .await?;
+
+    Ok(invalid)
+}
+
+pub async fn verify_assets(
+    assets_path: &Path,
+    version: &VersionInfo,
+) -> Result<Vec<InvalidFile>, LauncherError> {
+    let mut invalid = Vec::new();
+
+    let index_path = assets_path
+        .join("indexes")
+        .join(format!("{}.json", &version.asset_index.id));
+
+    verify_file(
+        index_path.clone(),
+        Some(&version.asset_index.sha1),
+        &mut invalid,
+    )
+    .await?;
+
+    // Without a valid index, the assets which should be present are unknown
+    if !invalid.is_empty() {
+        return Ok(invalid);
+    }
+
+    let index: AssetsIndex = serde_json::from_slice(&std::fs::read(index_path)?)?;
+
+    for asset in index.objects.values() {
+        verify_file(
+            assets_path
+                .join("objects")
+                .join(&asset.hash[..2])
+                .join(&asset.hash),
+            Some(&asset.hash),
+            &mut invalid,
+        )
+        .await?;
+    }
+
+    Ok(invalid)
+}
+
+pub async fn verify_libraries(
+    libraries_path: &Path,
+    libraries: &[Library],
+    platform: &Platform,
+) -> Result<Vec<InvalidFile>, LauncherError> {
+    let rule_context = RuleContext::new(platform.clone());
+    let mut invalid = Vec::new();
+
+    for library in libraries {
+        if let Some(rules) = &library.rules {
+            if !parse_rules(rules.as_slice(), &rule_context) {
+                continue;
+            }
+        }
 
-        save_file(path, &file)?;
+        if library.downloads.is_some() && get_library_artifact(library).is_none() {
+            continue;
+        }
 
-        Ok(file)
+        verify_file(
+            libraries_path.join(get_path_from_artifact(&library.name)?),
+            get_library_artifact(library).map(|x| &*x.sha1),
+            &mut invalid,
+        )
+        .await?;
     }
+
+    Ok(invalid)
 }
 
-fn save_file(path: &Path, bytes: &bytes::Bytes) -> Result<(), std::io::Error> {
+async fn verify_file(
+    path: PathBuf,
+    sha1: Option<&str>,
+    invalid: &mut Vec<InvalidFile>,
+) -> Result<(), LauncherError> {
+    if !path.exists() {
+        invalid.push(InvalidFile {
+            path,
+            status: FileStatus::Missing,
+        });
+
+        return Ok(());
+    }
+
+    if let Some(sha1) = sha1 {
+        let actual = hash_file(&path).await?.sha1;
+
+        if actual != sha1 {
+            invalid.push(InvalidFile {
+                path,
+                status: FileStatus::Corrupt {
+                    expected: sha1.to_string(),
+                    actual,
+                },
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a file by writing to a temporary file next to it which is then renamed, so a crash
+/// mid-write never leaves a partial file at `path`
+pub fn save_file(path: &Path, bytes: &bytes::Bytes) -> Result<(), std::io::Error> {
+    write_atomic(path, |temp_path| {
+        let mut file = File::create(temp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()
+    })
+}
+
+/// Copies a file, with the same guarantees as `save_file`
+fn copy_file(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    write_atomic(to, |temp_path| std::fs::copy(from, temp_path).map(|_| ()))
+}
+
+fn write_atomic<F>(path: &Path, write: F) -> Result<(), std::io::Error>
+where
+    F: FnOnce(&Path) -> Result<(), std::io::Error>,
+{
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)?;
     }
 
-    let mut file = File::create(path)?;
-    file.write_all(bytes)?;
+    let temp_path = get_temp_path(path);
 
-    Ok(())
+    let result = write(&temp_path).and_then(|_| std::fs::rename(&temp_path, path));
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+
+    result
 }
 
-pub fn get_os() -> Os {
-    match std::env::consts::OS {
-        "windows" => Os::Windows,
-        "macos" => Os::Osx,
-        "linux" => Os::Linux,
-        _ => Os::Unknown,
-    }
-}
-
-pub async fn download_file(url: &str, sha1: Option<&str>) -> Result<bytes::Bytes, LauncherError> {
-    let client = reqwest::Client::builder()
-        .tcp_keepalive(Some(std::time::Duration::from_secs(10)))
-        .build()
-        .map_err(|err| LauncherError::FetchError {
-            inner: err,
-            item: url.to_string(),
-        })?;
-
-    for attempt in 1..=4 {
-        let result = client.get(url).send().await;
-
-        match result {
-            Ok(x) => {
-                let bytes = x.bytes().await;
-
-                if let Ok(bytes) = bytes {
-                    if let Some(sha1) = sha1 {
-                        if &*get_hash(bytes.clone()).await? != sha1 {
-                            if attempt <= 3 {
-                                continue;
-                            } else {
-                                return Err(LauncherError::ChecksumFailure {
-                                    hash: sha1.to_string(),
-                                    url: url.to_string(),
-                                    tries: attempt,
-                                });
-                            }
-                        }
-                    }
-
-                    return Ok(bytes);
-                } else if attempt <= 3 {
-                    continue;
-                } else if let Err(err) = bytes {
-                    return Err(LauncherError::FetchError {
-                        inner: err,
-                        item: url.to_string(),
+fn get_temp_path(path: &Path) -> PathBuf {
+    path.with_file_name(format!(
+        "{}.{}.tmp",
+        path.file_name()
+            .map(|x| x.to_string_lossy())
+            .unwrap_or_default(),
+        Uuid::new_v4()
+    ))
+}
+
+pub async fn download_file(
+    context: &DownloadContext,
+    url: &str,
+    sha1: Option<&str>,
+) -> Result<bytes::Bytes, LauncherError> {
+    // The limiter is never closed, so failing to acquire a permit can't happen
+    let _permit = context.limiter.acquire().await.ok();
+
+    context
+        .retry
+        .run(url, |attempt| async move {
+            let response = context
+                .client
+                .get(url)
+                .send()
+                .await
+                .map_err(|err| retry_fetch(err, url))?;
+
+            let bytes = context
+                .retry
+                .check_response(response, url)?
+                .bytes()
+                .await
+                .map_err(|err| retry_fetch(err, url))?;
+
+            if let Some(sha1) = sha1 {
+                if &*get_hash(bytes.clone()).await? != sha1 {
+                    return Err(AttemptError::Retry {
+                        error: LauncherError::ChecksumFailure {
+                            hash: sha1.to_string(),
+                            url: url.to_string(),
+                            tries: attempt,
+                        },
+                        after: None,
                     });
                 }
             }
-            Err(_) if attempt <= 3 => continue,
-            Err(err) => {
-                return Err(LauncherError::FetchError {
-                    inner: err,
-                    item: url.to_string(),
-                })
+
+            Ok(bytes)
+        })
+        .await
+}
+
+/// Downloads a file straight to the disk, hashing it while it is written. The file only appears
+/// at `path` once it is complete and matches `sha1`, if given.
+///
+/// Until then, the file is kept at `<path>.part`. If a download is interrupted, the next attempt
+/// resumes from the end of the partial file when the server supports range requests.
+pub async fn download_to_file(
+    context: &DownloadContext,
+    url: &str,
+    path: &Path,
+    sha1: Option<&str>,
+) -> Result<FileHashes, LauncherError> {
+    let lock = context.get_path_lock(path);
+    let result = {
+        let _guard = lock.lock().await;
+
+        download_to_file_locked(context, url, path, sha1).await
+    };
+    context.release_path_lock(path, lock);
+
+    result
+}
+
+async fn download_to_file_locked(
+    context: &DownloadContext,
+    url: &str,
+    path: &Path,
+    sha1: Option<&str>,
+) -> Result<FileHashes, LauncherError> {
+    // Another download to the same path may have finished while this one waited for the lock
+    if let Some(sha1) = sha1 {
+        if path.exists() {
+            let hashes = hash_file(path).await?;
+
+            if hashes.sha1 == sha1 {
+                return Ok(hashes);
+            }
+        }
+    }
+
+    // The limiter is never closed, so failing to acquire a permit can't happen
+    let _permit = context.limiter.acquire().await.ok();
+
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let part_path = &*path.with_file_name(format!(
+        "{}.part",
+        path.file_name()
+            .map(|x| x.to_string_lossy())
+            .unwrap_or_default()
+    ));
+
+    context
+        .retry
+        .run(url, |attempt| async move {
+            let hashes = stream_to_file(context, url, part_path).await?;
+
+            if let Some(sha1) = sha1 {
+                if hashes.sha1 != sha1 {
+                    // The partial file can't be trusted anymore, so the next attempt starts
+                    // from the beginning
+                    tokio::fs::remove_file(part_path).await?;
+
+                    return Err(AttemptError::Retry {
+                        error: LauncherError::ChecksumFailure {
+                            hash: sha1.to_string(),
+                            url: url.to_string(),
+                            tries: attempt,
+                        },
+                        after: None,
+                    });
+                }
             }
+
+            tokio::fs::rename(part_path, path).await?;
+
+            Ok(hashes)
+        })
+        .await
+}
+
+/// Downloads a file to `path`, appending to it if it already contains the start of the file
+async fn stream_to_file(
+    context: &DownloadContext,
+    url: &str,
+    path: &Path,
+) -> Result<FileHashes, AttemptError> {
+    let (mut hasher, mut offset) = if path.exists() {
+        read_hasher(path.to_path_buf()).await?
+    } else {
+        (FileHasher::default(), 0)
+    };
+
+    let mut request = context.client.get(url);
+
+    if offset > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+    }
+
+    let mut response = request.send().await.map_err(|err| retry_fetch(err, url))?;
+
+    if offset > 0 && response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        // The partial file is at least as large as the file on the server, so it isn't a
+        // prefix of it
+        tokio::fs::remove_file(path).await?;
+
+        offset = 0;
+        response = context
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|err| retry_fetch(err, url))?;
+    }
+
+    let mut response = context.retry.check_response(response, url)?;
+
+    let resumed = offset > 0
+        && response.status() == reqwest::StatusCode::PARTIAL_CONTENT
+        && response
+            .headers()
+            .get(reqwest::header::CONTENT_RANGE)
+            .and_then(|x| x.to_str().ok())
+            .map(|x| x.starts_with(&*format!("bytes {}-", offset)))
+            .unwrap_or(false);
+
+    let mut file = if resumed {
+        tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .await?
+    } else if response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+        // The server answered with a range other than the requested one
+        tokio::fs::remove_file(path).await?;
+
+        return Err(AttemptError::Retry {
+            error: LauncherError::ParseError(format!(
+                "Server returned an unexpected range for {}",
+                url
+            )),
+            after: None,
+        });
+    } else {
+        // The server doesn't support ranges and sent the whole file
+        hasher = FileHasher::default();
+
+        tokio::fs::File::create(path).await?
+    };
+
+    loop {
+        let chunk = match context.read_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, response.chunk())
+                .await
+                .map_err(|_| AttemptError::Retry {
+                    error: std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!(
+                            "No data received from {} for {} seconds",
+                            url,
+                            timeout.as_secs()
+                        ),
+                    )
+                    .into(),
+                    after: None,
+                })?,
+            None => response.chunk().await,
+        };
+
+        let chunk = match chunk.map_err(|err| retry_fetch(err, url))? {
+            Some(chunk) => chunk,
+            None => break,
+        };
+
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+    }
+
+    file.sync_all().await?;
+
+    Ok(hasher.finish())
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// The hashes of a file, encoded as lowercase hex
+pub struct FileHashes {
+    /// The SHA1 hash of the file
+    pub sha1: String,
+    /// The SHA512 hash of the file
+    pub sha512: String,
+}
+
+#[derive(Default)]
+struct FileHasher {
+    sha1: sha1::Sha1,
+    sha512: sha2::Sha512,
+}
+
+impl FileHasher {
+    fn update(&mut self, bytes: &[u8]) {
+        self.sha1.update(bytes);
+        self.sha512.update(bytes);
+    }
+
+    fn finish(self) -> FileHashes {
+        FileHashes {
+            sha1: self.sha1.hexdigest(),
+            sha512: format!("{:x}", self.sha512.finalize()),
         }
     }
+}
 
-    unreachable!()
+/// Computes the hashes of a file without reading it into memory at once
+pub async fn hash_file(path: &Path) -> Result<FileHashes, LauncherError> {
+    let (hasher, _) = read_hasher(path.to_path_buf()).await?;
+
+    Ok(hasher.finish())
+}
+
+/// Feeds the contents of a file into a new hasher, returning it along with the file's size
+async fn read_hasher(path: PathBuf) -> Result<(FileHasher, u64), LauncherError> {
+    // The limiter is never closed, so failing to acquire a permit can't happen
+    let _permit = HASH_LIMITER.acquire().await.ok();
+
+    tokio::task::spawn_blocking(move || {
+        let mut file = File::open(path)?;
+        let mut hasher = FileHasher::default();
+        let mut buffer = vec![0; 64 * 1024];
+        let mut size = 0;
+
+        loop {
+            let read = file.read(&mut buffer)?;
+
+            if read == 0 {
+                break;
+            }
+
+            hasher.update(&buffer[..read]);
+            size += read as u64;
+        }
+
+        Ok((hasher, size))
+    })
+    .await?
 }
 
 /// Computes a checksum of the input bytes
@@ -321,3 +1100,99 @@ pub async fn get_hash(bytes: bytes::Bytes) -> Result<String, LauncherError> {
 
     Ok(hash)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("theseus-natives-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_archive(path: &Path, entries: &[(&str, &str)]) {
+        let mut archive = zip::ZipWriter::new(File::create(path).unwrap());
+
+        for (name, contents) in entries {
+            archive
+                .start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            archive.write_all(contents.as_bytes()).unwrap();
+        }
+
+        archive.finish().unwrap();
+    }
+
+    #[test]
+    fn skips_excluded_entries() {
+        let dir = temp_dir();
+        let archive_path = dir.join("natives.jar");
+        let natives_path = dir.join("natives");
+        write_archive(
+            &archive_path,
+            &[
+                ("liblwjgl.so", "native"),
+                ("META-INF/MANIFEST.MF", "manifest"),
+            ],
+        );
+
+        extract_native(
+            &archive_path,
+            &natives_path,
+            &["META-INF/".to_string()],
+            "abc",
+        )
+        .unwrap();
+
+        assert!(natives_path.join("liblwjgl.so").exists());
+        assert!(!natives_path.join("META-INF").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn drops_entries_escaping_the_natives_directory() {
+        let dir = temp_dir();
+        let archive_path = dir.join("natives.jar");
+        let natives_path = dir.join("natives");
+        write_archive(
+            &archive_path,
+            &[("../escaped.so", "escaped"), ("liblwjgl.so", "native")],
+        );
+
+        extract_native(&archive_path, &natives_path, &[], "abc").unwrap();
+
+        assert!(natives_path.join("liblwjgl.so").exists());
+        assert!(!dir.join("escaped.so").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn skips_archives_extracted_before() {
+        let dir = temp_dir();
+        let archive_path = dir.join("natives.jar");
+        let natives_path = dir.join("natives");
+        let native_path = natives_path.join("liblwjgl.so");
+        write_archive(&archive_path, &[("liblwjgl.so", "native")]);
+
+        extract_native(&archive_path, &natives_path, &[], "abc").unwrap();
+        assert!(natives_path.join(".extracted").join("abc").exists());
+
+        // An archive with the same hash isn't extracted again while its files are present
+        std::fs::write(&native_path, "modified").unwrap();
+        extract_native(&archive_path, &natives_path, &[], "abc").unwrap();
+        assert_eq!(std::fs::read_to_string(&native_path).unwrap(), "modified");
+
+        // A changed archive or a missing file is extracted again
+        extract_native(&archive_path, &natives_path, &[], "def").unwrap();
+        assert_eq!(std::fs::read_to_string(&native_path).unwrap(), "native");
+
+        std::fs::remove_file(&native_path).unwrap();
+        extract_native(&archive_path, &natives_path, &[], "def").unwrap();
+        assert_eq!(std::fs::read_to_string(&native_path).unwrap(), "native");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+use crate::launcher::LauncherError;
+use std::time::Duration;
+
+/// The user agent sent with every request of the launcher by default
+pub const DEFAULT_USER_AGENT: &str = concat!("theseus/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Clone)]
+/// Settings of the HTTP client shared by all downloads, metadata fetches and authentication calls
+pub struct HttpSettings {
+    /// The user agent sent with every request
+    pub user_agent: String,
+    /// The URL of a proxy all requests are sent through
+    pub proxy: Option<String>,
+    /// How long connecting to a server may take
+    pub connect_timeout: Option<Duration>,
+    /// How long a whole request may take, including reading the response body
+    pub timeout: Option<Duration>,
+    /// How long a download may wait for the next part of a file before it is retried
+    pub read_timeout: Option<Duration>,
+    /// How long a single request for a manifest may take. Once it runs out, the cached copy of
+    /// the manifest is used instead
+    pub metadata_timeout: Option<Duration>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        HttpSettings {
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            proxy: None,
+            connect_timeout: Some(Duration::from_secs(10)),
+            timeout: None,
+            read_timeout: Some(Duration::from_secs(30)),
+            metadata_timeout: Some(Duration::from_secs(15)),
+        }
+    }
+}
+
+impl HttpSettings {
+    /// How long a single request for a manifest may take, which is never longer than a whole
+    /// request may take
+    pub(crate) fn get_metadata_timeout(&self) -> Option<Duration> {
+        match (self.metadata_timeout, self.timeout) {
+            (Some(metadata), Some(timeout)) => Some(metadata.min(timeout)),
+            (metadata, timeout) => metadata.or(timeout),
+        }
+    }
+
+    /// Builds a client from the settings. The client holds a connection pool, so it should be
+    /// built once and cloned wherever it is needed
+    pub fn build(&self) -> Result<reqwest::Client, LauncherError> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(&*self.user_agent)
+            .tcp_keepalive(Some(Duration::from_secs(10)));
+
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy).map_err(|err| {
+                LauncherError::FetchError {
+                    inner: err,
+                    item: format!("proxy {}", proxy),
+                }
+            })?);
+        }
+
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        builder.build().map_err(|err| LauncherError::FetchError {
+            inner: err,
+            item: "HTTP client".to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(timeout: Option<u64>, metadata_timeout: Option<u64>) -> HttpSettings {
+        HttpSettings {
+            timeout: timeout.map(Duration::from_secs),
+            metadata_timeout: metadata_timeout.map(Duration::from_secs),
+            ..HttpSettings::default()
+        }
+    }
+
+    #[test]
+    fn metadata_timeout_never_exceeds_the_request_timeout() {
+        let timeout = |timeout, metadata_timeout| {
+            settings(timeout, metadata_timeout)
+                .get_metadata_timeout()
+                .map(|x| x.as_secs())
+        };
+
+        assert_eq!(timeout(None, Some(15)), Some(15));
+        assert_eq!(timeout(Some(60), Some(15)), Some(15));
+        assert_eq!(timeout(Some(5), Some(15)), Some(5));
+        assert_eq!(timeout(Some(5), None), Some(5));
+        assert_eq!(timeout(None, None), None);
+    }
+}
@@ -0,0 +1,72 @@
+use chrono::Utc;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// The log of a single installation, stored in the `logs/installs` folder of the launcher's root
+/// directory. Failing to create or write to the log never fails the installation
+pub(crate) struct InstallLog {
+    file: Option<File>,
+}
+
+impl InstallLog {
+    /// Creates the log of an installation of a version. If the log can't be created, nothing is
+    /// logged
+    pub fn create(root_dir: &Path, version_name: &str) -> Self {
+        InstallLog {
+            file: open_log_file(root_dir, version_name).ok(),
+        }
+    }
+
+    /// Writes a timestamped message to the log
+    pub fn write(&mut self, message: impl AsRef<str>) {
+        if let Some(file) = &mut self.file {
+            let _ = writeln!(
+                file,
+                "[{}] {}",
+                Utc::now().format("%H:%M:%S"),
+                message.as_ref()
+            );
+        }
+    }
+
+    /// Writes the output of a process to the log, if there is any
+    pub fn write_output(&mut self, name: &str, output: &str) {
+        if output.trim().is_empty() {
+            return;
+        }
+
+        self.write(format!("{}:", name));
+        if let Some(file) = &mut self.file {
+            let _ = writeln!(file, "{}", output.trim_end());
+        }
+    }
+}
+
+/// Creates a new log file named after the version and the time, which never replaces the log of
+/// another installation started at the same time
+fn open_log_file(root_dir: &Path, version_name: &str) -> std::io::Result<File> {
+    let dir = root_dir.join("logs").join("installs");
+    std::fs::create_dir_all(&dir)?;
+
+    let name = format!(
+        "{}-{}",
+        version_name,
+        Utc::now().format("%Y-%m-%d-%H-%M-%S")
+    );
+
+    let mut attempt = 0;
+    loop {
+        let path = match attempt {
+            0 => dir.join(format!("{}.log", name)),
+            attempt => dir.join(format!("{}-{}.log", name, attempt)),
+        };
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists && attempt < 100 => {
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
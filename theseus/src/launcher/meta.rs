@@ -0,0 +1,693 @@
+use crate::launcher::download::{save_file, DownloadContext};
+use crate::launcher::retry::{retry_fetch, AttemptError};
+use crate::launcher::{LauncherError, ModLoader};
+use chrono::{DateTime, Utc};
+use daedalus::minecraft::VersionManifest;
+use daedalus::modded::{LoaderType, LoaderVersion, Manifest};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The default metadata server used by the launcher
+pub const META_URL: &str = "https://staging-cdn.modrinth.com/gamedata";
+
+/// How long a cached manifest is used before it is revalidated with the metadata server
+pub const DEFAULT_METADATA_TTL: Duration = Duration::from_secs(60 * 60);
+
+const MINECRAFT_MANIFEST: &str = "minecraft/v0/manifest.json";
+const FORGE_MANIFEST: &str = "forge/v0/manifest.json";
+const FABRIC_MANIFEST: &str = "fabric/v0/manifest.json";
+const QUILT_MANIFEST: &str = "quilt/v0/manifest.json";
+const NEOFORGE_MANIFEST: &str = "neo/v0/manifest.json";
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// The location game and mod loader manifests are read from
+pub enum MetadataSource {
+    /// A metadata server (or mirror of one), identified by its base URL
+    Remote(String),
+    /// A local directory laid out the same way as the metadata server,
+    /// e.g. `<dir>/minecraft/v0/manifest.json`
+    Local(PathBuf),
+}
+
+impl Default for MetadataSource {
+    fn default() -> Self {
+        MetadataSource::Remote(META_URL.to_string())
+    }
+}
+
+#[derive(Debug)]
+/// The manifests of the game and of every supported mod loader
+pub struct Metadata {
+    /// The versions of the game
+    pub minecraft: VersionManifest,
+    /// The Forge versions of every game version
+    pub forge: Manifest,
+    /// The Fabric versions of every game version
+    pub fabric: Manifest,
+    /// The Quilt versions of every game version, or `None` if the source doesn't provide them
+    pub quilt: Option<Manifest>,
+    /// The NeoForge versions of every game version, or `None` if the source doesn't provide them
+    pub neoforge: Option<Manifest>,
+}
+
+impl Metadata {
+    /// Gets the manifest of a mod loader, or `None` for vanilla and loaders the source doesn't
+    /// provide
+    pub fn get_loader_manifest(&self, loader: ModLoader) -> Option<&Manifest> {
+        match loader {
+            ModLoader::Vanilla => None,
+            ModLoader::Forge => Some(&self.forge),
+            ModLoader::Fabric => Some(&self.fabric),
+            ModLoader::Quilt => self.quilt.as_ref(),
+            ModLoader::NeoForge => self.neoforge.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+/// Which version of a mod loader is installed for a game version
+pub enum LoaderVersionSelector {
+    /// The newest version of the loader
+    Latest,
+    /// The version the loader marks as stable
+    Stable,
+    /// The stable version, or the newest one if the loader has no stable version
+    #[default]
+    Recommended,
+    /// A specific version, either by its full ID (e.g. `1.18.2-forge-40.1.0`) or by the version
+    /// of the loader alone (e.g. `40.1.0`). The manifest only lists the latest and stable version
+    /// of each game version, so other versions are looked up in the version files of the
+    /// metadata server
+    Exact(String),
+}
+
+#[derive(Debug, Clone)]
+/// An on-disk cache of manifests fetched from a remote metadata source
+pub struct MetadataCache {
+    /// The directory the cached manifests are stored in, in a folder per metadata server
+    pub path: PathBuf,
+    /// How long a cached manifest is used without asking the server whether it changed
+    pub ttl: Duration,
+}
+
+impl MetadataCache {
+    /// Creates a cache stored in the `meta` folder of the launcher's root directory
+    pub fn new(root_dir: &Path) -> Self {
+        MetadataCache {
+            path: root_dir.join("meta"),
+            ttl: DEFAULT_METADATA_TTL,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CacheEntry {
+    etag: Option<String>,
+    fetched: DateTime<Utc>,
+}
+
+impl CacheEntry {
+    fn is_fresh(&self, ttl: Duration) -> bool {
+        Utc::now()
+            .signed_duration_since(self.fetched)
+            .to_std()
+            .map(|age| age < ttl)
+            .unwrap_or(false)
+    }
+}
+
+impl MetadataSource {
+    async fn fetch<T: DeserializeOwned>(
+        &self,
+        context: &DownloadContext,
+        cache: Option<&MetadataCache>,
+        manifest: &str,
+    ) -> Result<T, LauncherError> {
+        match self {
+            MetadataSource::Remote(base_url) => {
+                let base_url = base_url.trim_end_matches('/');
+                let url = format!("{}/{}", base_url, manifest);
+
+                fetch_remote(context, cache, base_url, &url, manifest).await
+            }
+            MetadataSource::Local(dir) => {
+                let path = dir.join(manifest);
+
+                let bytes = tokio::fs::read(&path).await.map_err(|err| {
+                    LauncherError::InvalidInput(format!(
+                        "Unable to read manifest at {}: {}",
+                        path.to_string_lossy(),
+                        err
+                    ))
+                })?;
+
+                Ok(serde_json::from_slice(&bytes)?)
+            }
+        }
+    }
+
+    /// Fetches a manifest the source may not provide, returning `None` if it doesn't
+    async fn fetch_optional<T: DeserializeOwned>(
+        &self,
+        context: &DownloadContext,
+        cache: Option<&MetadataCache>,
+        manifest: &str,
+    ) -> Result<Option<T>, LauncherError> {
+        if let MetadataSource::Local(dir) = self {
+            if !dir.join(manifest).exists() {
+                return Ok(None);
+            }
+        }
+
+        match self.fetch(context, cache, manifest).await {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if is_not_found(&err) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Whether a manifest couldn't be fetched because the metadata server doesn't have it
+fn is_not_found(err: &LauncherError) -> bool {
+    match err {
+        LauncherError::HttpError { status, .. } => *status == reqwest::StatusCode::NOT_FOUND,
+        LauncherError::MetadataUnavailable { inner, .. } => is_not_found(inner),
+        _ => false,
+    }
+}
+
+/// Fetches the Minecraft, Forge, Fabric, Quilt and NeoForge manifests from the given source.
+/// Sources which predate Quilt and NeoForge may not provide their manifests, which are then
+/// left out. Any other error fetching them is returned.
+///
+/// When a cache is given, remote manifests are stored in it and reused until their TTL expires.
+/// Expired manifests are revalidated using their ETag, and are still used if the metadata
+/// server can't be reached.
+pub async fn fetch_metadata(
+    context: &DownloadContext,
+    source: &MetadataSource,
+    cache: Option<&MetadataCache>,
+) -> Result<Metadata, LauncherError> {
+    let (minecraft, forge, fabric, quilt, neoforge) = futures::future::join5(
+        fetch_version_manifest(context, source, cache),
+        source.fetch(context, cache, FORGE_MANIFEST),
+        source.fetch(context, cache, FABRIC_MANIFEST),
+        source.fetch_optional(context, cache, QUILT_MANIFEST),
+        source.fetch_optional(context, cache, NEOFORGE_MANIFEST),
+    )
+    .await;
+
+    Ok(Metadata {
+        minecraft: minecraft?,
+        forge: forge?,
+        fabric: fabric?,
+        quilt: quilt?,
+        neoforge: neoforge?,
+    })
+}
+
+/// Fetches the manifest of the game versions from the given source
+pub(crate) async fn fetch_version_manifest(
+    context: &DownloadContext,
+    source: &MetadataSource,
+    cache: Option<&MetadataCache>,
+) -> Result<VersionManifest, LauncherError> {
+    source.fetch(context, cache, MINECRAFT_MANIFEST).await
+}
+
+/// Fetches the manifest of a mod loader from the given source, or `None` for vanilla
+pub(crate) async fn fetch_loader_manifest(
+    context: &DownloadContext,
+    source: &MetadataSource,
+    cache: Option<&MetadataCache>,
+    loader: ModLoader,
+) -> Result<Option<Manifest>, LauncherError> {
+    let manifest = match loader {
+        ModLoader::Vanilla => return Ok(None),
+        ModLoader::Forge => FORGE_MANIFEST,
+        ModLoader::Fabric => FABRIC_MANIFEST,
+        ModLoader::Quilt => QUILT_MANIFEST,
+        ModLoader::NeoForge => NEOFORGE_MANIFEST,
+    };
+
+    Ok(Some(source.fetch(context, cache, manifest).await?))
+}
+
+async fn fetch_remote<T: DeserializeOwned>(
+    context: &DownloadContext,
+    cache: Option<&MetadataCache>,
+    base_url: &str,
+    url: &str,
+    manifest: &str,
+) -> Result<T, LauncherError> {
+    // Every server gets its own folder, so switching servers doesn't mix up their manifests
+    let paths = cache.map(|cache| {
+        let dir = cache.path.join(sha1::Sha1::from(base_url).hexdigest());
+
+        (dir.join(manifest), dir.join(format!("{}.cache", manifest)))
+    });
+
+    let cached = paths
+        .as_ref()
+        .and_then(|(path, entry_path)| read_cached::<T>(path, entry_path));
+
+    let (cached, etag) = match cached {
+        Some((value, entry)) if cache.map(|x| entry.is_fresh(x.ttl)).unwrap_or(false) => {
+            return Ok(value)
+        }
+        Some((value, entry)) => (Some(value), entry.etag),
+        None => (None, None),
+    };
+
+    match fetch_manifest(context, url, etag.as_deref()).await {
+        Ok(Some((bytes, etag))) => match serde_json::from_slice(&bytes) {
+            Ok(value) => {
+                if let Some((path, entry_path)) = &paths {
+                    save_file(path, &bytes)?;
+                    save_entry(entry_path, etag)?;
+                }
+
+                Ok(value)
+            }
+            Err(err) => cached.ok_or_else(|| err.into()),
+        },
+        Ok(None) => {
+            let value = cached.ok_or_else(|| {
+                LauncherError::ParseError(format!(
+                    "Metadata server returned not modified for {} without a cached copy",
+                    url
+                ))
+            })?;
+
+            if let Some((_, entry_path)) = &paths {
+                save_entry(entry_path, etag)?;
+            }
+
+            Ok(value)
+        }
+        Err(err) => cached.ok_or_else(|| LauncherError::MetadataUnavailable {
+            inner: Box::new(err),
+            item: url.to_string(),
+        }),
+    }
+}
+
+/// Fetches a manifest, returning `None` if the server reports the cached copy is up to date
+async fn fetch_manifest(
+    context: &DownloadContext,
+    url: &str,
+    etag: Option<&str>,
+) -> Result<Option<(bytes::Bytes, Option<String>)>, LauncherError> {
+    context
+        .retry
+        .run(url, |_| async move {
+            let mut request = context.client.get(url);
+
+            if let Some(timeout) = context.metadata_timeout() {
+                request = request.timeout(timeout);
+            }
+
+            if let Some(etag) = etag {
+                request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+            }
+
+            let response = request.send().await.map_err(|err| retry_fetch(err, url))?;
+
+            if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+                return Ok(None);
+            }
+
+            let response = context.retry.check_response(response, url)?;
+
+            let etag = response
+                .headers()
+                .get(reqwest::header::ETAG)
+                .and_then(|x| x.to_str().ok())
+                .map(|x| x.to_string());
+
+            let bytes = response
+                .bytes()
+                .await
+                .map_err(|err| retry_fetch(err, url))?;
+
+            Ok(Some((bytes, etag)))
+        })
+        .await
+}
+
+fn read_cached<T: DeserializeOwned>(path: &Path, entry_path: &Path) -> Option<(T, CacheEntry)> {
+    let value = serde_json::from_slice(&std::fs::read(path).ok()?).ok()?;
+    let entry = serde_json::from_slice(&std::fs::read(entry_path).ok()?).ok()?;
+
+    Some((value, entry))
+}
+
+fn save_entry(path: &Path, etag: Option<String>) -> Result<(), LauncherError> {
+    let entry = CacheEntry {
+        etag,
+        fetched: Utc::now(),
+    };
+
+    save_file(path, &bytes::Bytes::from(serde_json::to_vec(&entry)?))?;
+
+    Ok(())
+}
+
+/// Picks the version of a mod loader for a game version from the manifest of the loader
+pub(crate) fn select_loader_version(
+    manifest: &Manifest,
+    loader: ModLoader,
+    game_version: &str,
+    selector: &LoaderVersionSelector,
+) -> Result<LoaderVersion, LauncherError> {
+    let loaders = &manifest
+        .game_versions
+        .iter()
+        .find(|x| x.id == game_version)
+        .ok_or_else(|| {
+            LauncherError::InvalidInput(format!(
+                "Version {} for mod loader {:?} does not exist",
+                game_version, loader
+            ))
+        })?
+        .loaders;
+
+    let selected = match selector {
+        LoaderVersionSelector::Latest => loaders.get(&LoaderType::Latest),
+        LoaderVersionSelector::Stable => loaders.get(&LoaderType::Stable),
+        LoaderVersionSelector::Recommended => loaders
+            .get(&LoaderType::Stable)
+            .or_else(|| loaders.get(&LoaderType::Latest)),
+        LoaderVersionSelector::Exact(version) => loaders
+            .values()
+            .find(|x| x.id == *version || x.id.ends_with(&format!("-{}", version))),
+    };
+
+    selected.cloned().ok_or_else(|| {
+        let mut available = loaders.values().map(|x| x.id.clone()).collect::<Vec<_>>();
+        available.sort();
+        available.dedup();
+
+        LauncherError::LoaderVersionNotFound {
+            loader: format!("{:?}", loader),
+            game_version: game_version.to_string(),
+            requested: match selector {
+                LoaderVersionSelector::Exact(version) => version.clone(),
+                selector => format!("{:?}", selector).to_lowercase(),
+            },
+            available,
+        }
+    })
+}
+
+/// Resolves the version of a mod loader for a game version. Exact versions the manifest doesn't
+/// list are looked up next to the version files of the listed ones
+pub(crate) async fn resolve_loader_version(
+    context: &DownloadContext,
+    manifest: &Manifest,
+    loader: ModLoader,
+    game_version: &str,
+    selector: &LoaderVersionSelector,
+) -> Result<LoaderVersion, LauncherError> {
+    let err = match select_loader_version(manifest, loader, game_version, selector) {
+        Ok(version) => return Ok(version),
+        Err(err) => err,
+    };
+
+    let version = match selector {
+        LoaderVersionSelector::Exact(version) => version,
+        _ => return Err(err),
+    };
+    let known = match manifest
+        .game_versions
+        .iter()
+        .find(|x| x.id == game_version)
+        .and_then(|x| x.loaders.values().next())
+    {
+        Some(known) => known,
+        None => return Err(err),
+    };
+
+    let base_url = known
+        .url
+        .strip_suffix(&format!("{}.json", known.id))
+        .unwrap_or_else(|| &known.url[..known.url.rfind('/').map(|x| x + 1).unwrap_or(0)]);
+
+    // Version files are named after the full ID, which usually starts with the game version
+    let mut ids = vec![version.clone()];
+    if !version.starts_with(game_version) {
+        ids.push(format!("{}-{}", game_version, version));
+        ids.push(format!(
+            "{}-{}-{}",
+            game_version,
+            format!("{:?}", loader).to_lowercase(),
+            version
+        ));
+    }
+
+    for id in ids {
+        let url = format!("{}{}.json", base_url, id);
+
+        if version_file_exists(context, &url).await? {
+            return Ok(LoaderVersion { id, url });
+        }
+    }
+
+    Err(err)
+}
+
+async fn version_file_exists(context: &DownloadContext, url: &str) -> Result<bool, LauncherError> {
+    context
+        .retry
+        .run(url, |_| async move {
+            let mut request = context.client.head(url);
+
+            if let Some(timeout) = context.metadata_timeout() {
+                request = request.timeout(timeout);
+            }
+
+            let response = request.send().await.map_err(|err| retry_fetch(err, url))?;
+
+            // Storage buckets which can't be listed report missing files as forbidden
+            if matches!(
+                response.status(),
+                reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::FORBIDDEN
+            ) {
+                return Ok::<_, AttemptError>(false);
+            }
+
+            context.retry.check_response(response, url)?;
+
+            Ok(true)
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use daedalus::modded::Version;
+    use std::collections::HashMap;
+
+    fn loader_version(id: &str) -> LoaderVersion {
+        LoaderVersion {
+            id: id.to_string(),
+            url: format!("https://meta.example.com/forge/v0/versions/{}.json", id),
+        }
+    }
+
+    fn manifest(latest: &str, stable: Option<&str>) -> Manifest {
+        let mut loaders = HashMap::new();
+        loaders.insert(LoaderType::Latest, loader_version(latest));
+        if let Some(stable) = stable {
+            loaders.insert(LoaderType::Stable, loader_version(stable));
+        }
+
+        Manifest {
+            game_versions: vec![Version {
+                id: "1.18.2".to_string(),
+                loaders,
+            }],
+        }
+    }
+
+    fn select(
+        manifest: &Manifest,
+        selector: LoaderVersionSelector,
+    ) -> Result<String, LauncherError> {
+        select_loader_version(manifest, ModLoader::Forge, "1.18.2", &selector).map(|x| x.id)
+    }
+
+    fn cache_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("theseus-meta-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn cache_entries_expire_after_their_ttl() {
+        let entry = CacheEntry {
+            etag: None,
+            fetched: Utc::now() - chrono::Duration::minutes(30),
+        };
+
+        assert!(entry.is_fresh(Duration::from_secs(60 * 60)));
+        assert!(!entry.is_fresh(Duration::from_secs(10 * 60)));
+    }
+
+    #[test]
+    fn cache_entries_from_the_future_are_stale() {
+        let entry = CacheEntry {
+            etag: None,
+            fetched: Utc::now() + chrono::Duration::minutes(5),
+        };
+
+        assert!(!entry.is_fresh(Duration::from_secs(60 * 60)));
+    }
+
+    #[test]
+    fn reads_cached_manifests_with_their_entry() {
+        let dir = cache_dir();
+        let path = dir.join("manifest.json");
+        let entry_path = dir.join("manifest.json.cache");
+
+        std::fs::write(&path, r#"{"versions":["1.18.2"]}"#).unwrap();
+        save_entry(&entry_path, Some("\"etag\"".to_string())).unwrap();
+
+        let (value, entry) = read_cached::<serde_json::Value>(&path, &entry_path).unwrap();
+        assert_eq!(value["versions"][0], "1.18.2");
+        assert_eq!(entry.etag.as_deref(), Some("\"etag\""));
+        assert!(entry.is_fresh(DEFAULT_METADATA_TTL));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cached_manifests_without_an_entry_or_invalid_are_ignored() {
+        let dir = cache_dir();
+        let path = dir.join("manifest.json");
+        let entry_path = dir.join("manifest.json.cache");
+
+        std::fs::write(&path, "{}").unwrap();
+        assert!(read_cached::<serde_json::Value>(&path, &entry_path).is_none());
+
+        save_entry(&entry_path, None).unwrap();
+        std::fs::write(&path, "{not json").unwrap();
+        assert!(read_cached::<serde_json::Value>(&path, &entry_path).is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn selects_by_policy() {
+        let manifest = manifest("1.18.2-forge-40.1.0", Some("1.18.2-forge-40.0.0"));
+
+        assert_eq!(
+            select(&manifest, LoaderVersionSelector::Latest).unwrap(),
+            "1.18.2-forge-40.1.0"
+        );
+        assert_eq!(
+            select(&manifest, LoaderVersionSelector::Stable).unwrap(),
+            "1.18.2-forge-40.0.0"
+        );
+        assert_eq!(
+            select(&manifest, LoaderVersionSelector::Recommended).unwrap(),
+            "1.18.2-forge-40.0.0"
+        );
+    }
+
+    #[test]
+    fn recommended_falls_back_to_latest() {
+        let manifest = manifest("1.18.2-forge-40.1.0", None);
+
+        assert_eq!(
+            select(&manifest, LoaderVersionSelector::Recommended).unwrap(),
+            "1.18.2-forge-40.1.0"
+        );
+        assert!(matches!(
+            select(&manifest, LoaderVersionSelector::Stable),
+            Err(LauncherError::LoaderVersionNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn selects_exact_versions() {
+        let manifest = manifest("1.18.2-forge-40.1.0", Some("1.18.2-forge-40.0.0"));
+
+        assert_eq!(
+            select(
+                &manifest,
+                LoaderVersionSelector::Exact("1.18.2-forge-40.0.0".to_string())
+            )
+            .unwrap(),
+            "1.18.2-forge-40.0.0"
+        );
+        assert_eq!(
+            select(
+                &manifest,
+                LoaderVersionSelector::Exact("40.1.0".to_string())
+            )
+            .unwrap(),
+            "1.18.2-forge-40.1.0"
+        );
+    }
+
+    #[test]
+    fn missing_exact_versions_list_the_available_ones() {
+        let manifest = manifest("1.18.2-forge-40.1.0", Some("1.18.2-forge-40.1.0"));
+
+        match select(
+            &manifest,
+            LoaderVersionSelector::Exact("39.0.0".to_string()),
+        ) {
+            Err(LauncherError::LoaderVersionNotFound {
+                requested,
+                available,
+                ..
+            }) => {
+                assert_eq!(requested, "39.0.0");
+                assert_eq!(available, vec!["1.18.2-forge-40.1.0"]);
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn missing_game_versions_are_invalid() {
+        let manifest = manifest("1.18.2-forge-40.1.0", None);
+
+        assert!(matches!(
+            select_loader_version(
+                &manifest,
+                ModLoader::Forge,
+                "1.17.1",
+                &LoaderVersionSelector::Latest
+            ),
+            Err(LauncherError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn only_missing_manifests_are_not_found() {
+        let not_found = LauncherError::HttpError {
+            status: reqwest::StatusCode::NOT_FOUND,
+            url: "https://example.com/quilt/v0/manifest.json".to_string(),
+        };
+        let server_error = LauncherError::HttpError {
+            status: reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+            url: "https://example.com/quilt/v0/manifest.json".to_string(),
+        };
+
+        assert!(is_not_found(&not_found));
+        assert!(!is_not_found(&server_error));
+        assert!(is_not_found(&LauncherError::MetadataUnavailable {
+            inner: Box::new(not_found),
+            item: "https://example.com/quilt/v0/manifest.json".to_string(),
+        }));
+        assert!(!is_not_found(&LauncherError::ParseError(
+            "Invalid manifest".to_string()
+        )));
+    }
+}
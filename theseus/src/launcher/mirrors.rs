@@ -0,0 +1,159 @@
+/// The upstream base URL of asset objects
+pub const ASSETS_URL: &str = "https://resources.download.minecraft.net/";
+/// The upstream base URL of Mojang's library repository
+pub const LIBRARIES_URL: &str = "https://libraries.minecraft.net/";
+/// The upstream base URL of the Forge maven repository
+pub const FORGE_MAVEN_URL: &str = "https://maven.minecraftforge.net/";
+/// The address the Forge maven repository used to be hosted at, which older versions still use
+const LEGACY_FORGE_MAVEN_URL: &str = "https://files.minecraftforge.net/maven/";
+
+#[derive(Debug, Clone)]
+/// Ordered lists of base URLs files are downloaded from. The mirrors of a list are tried in order
+/// until one of them succeeds, so an upstream URL can be kept at the end as a fallback
+pub struct Mirrors {
+    /// Mirrors of the asset object store at [`ASSETS_URL`]
+    pub assets: Vec<String>,
+    /// Mirrors of Mojang's library repository at [`LIBRARIES_URL`]
+    pub libraries: Vec<String>,
+    /// Mirrors of the Forge maven repository at [`FORGE_MAVEN_URL`]
+    pub forge: Vec<String>,
+    /// Maven repositories tried before the repository a library names itself
+    pub maven: Vec<String>,
+}
+
+impl Default for Mirrors {
+    fn default() -> Self {
+        Mirrors {
+            assets: vec![ASSETS_URL.to_string()],
+            libraries: vec![LIBRARIES_URL.to_string()],
+            forge: vec![FORGE_MAVEN_URL.to_string()],
+            maven: Vec::new(),
+        }
+    }
+}
+
+impl Mirrors {
+    /// Gets the URLs a file is downloaded from, in order. URLs pointing at one of the upstream
+    /// repositories are redirected to its mirrors, and any other URL is used as is
+    pub fn get_urls(&self, url: &str) -> Vec<String> {
+        self.get_mirrored_urls(url)
+            .unwrap_or_else(|| vec![url.to_string()])
+    }
+
+    /// Gets the URLs of an artifact of a Maven repository, in order
+    pub fn get_maven_urls(&self, repository: &str, path: &str) -> Vec<String> {
+        let url = join_url(repository, path);
+
+        self.get_mirrored_urls(&url).unwrap_or_else(|| {
+            self.maven
+                .iter()
+                .map(|x| join_url(x, path))
+                .chain(std::iter::once(url))
+                .collect()
+        })
+    }
+
+    fn get_mirrored_urls(&self, url: &str) -> Option<Vec<String>> {
+        let repositories = [
+            (ASSETS_URL, &self.assets),
+            (LIBRARIES_URL, &self.libraries),
+            (FORGE_MAVEN_URL, &self.forge),
+            (LEGACY_FORGE_MAVEN_URL, &self.forge),
+        ];
+
+        repositories
+            .iter()
+            .filter(|(_, mirrors)| !mirrors.is_empty())
+            .find_map(|(upstream, mirrors)| {
+                let path = url.strip_prefix(upstream)?;
+
+                Some(mirrors.iter().map(|x| join_url(x, path)).collect())
+            })
+    }
+}
+
+fn join_url(base: &str, path: &str) -> String {
+    format!(
+        "{}/{}",
+        base.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mirrors() -> Mirrors {
+        Mirrors {
+            assets: vec!["https://assets.example.com/".to_string()],
+            libraries: vec![
+                "https://libraries.example.com".to_string(),
+                LIBRARIES_URL.to_string(),
+            ],
+            forge: vec!["https://forge.example.com/".to_string()],
+            maven: vec!["https://maven.example.com".to_string()],
+        }
+    }
+
+    #[test]
+    fn urls_of_upstream_repositories_are_mirrored() {
+        let mirrors = mirrors();
+
+        assert_eq!(
+            mirrors.get_urls("https://resources.download.minecraft.net/ab/abcdef"),
+            vec!["https://assets.example.com/ab/abcdef"]
+        );
+        assert_eq!(
+            mirrors.get_urls("https://libraries.minecraft.net/org/lwjgl/lwjgl.jar"),
+            vec![
+                "https://libraries.example.com/org/lwjgl/lwjgl.jar",
+                "https://libraries.minecraft.net/org/lwjgl/lwjgl.jar",
+            ]
+        );
+        assert_eq!(
+            mirrors.get_urls("https://files.minecraftforge.net/maven/net/minecraftforge/forge.jar"),
+            vec!["https://forge.example.com/net/minecraftforge/forge.jar"]
+        );
+    }
+
+    #[test]
+    fn other_urls_are_kept() {
+        assert_eq!(
+            mirrors().get_urls("https://example.org/file.jar"),
+            vec!["https://example.org/file.jar"]
+        );
+    }
+
+    #[test]
+    fn upstream_urls_without_mirrors_are_kept() {
+        let mirrors = Mirrors {
+            assets: Vec::new(),
+            ..Mirrors::default()
+        };
+
+        assert_eq!(
+            mirrors.get_urls("https://resources.download.minecraft.net/ab/abcdef"),
+            vec!["https://resources.download.minecraft.net/ab/abcdef"]
+        );
+    }
+
+    #[test]
+    fn maven_urls_try_maven_mirrors_first() {
+        assert_eq!(
+            mirrors().get_maven_urls("https://maven.fabricmc.net/", "/net/fabricmc/loader.jar"),
+            vec![
+                "https://maven.example.com/net/fabricmc/loader.jar",
+                "https://maven.fabricmc.net/net/fabricmc/loader.jar",
+            ]
+        );
+    }
+
+    #[test]
+    fn maven_urls_of_upstream_repositories_are_mirrored() {
+        assert_eq!(
+            mirrors().get_maven_urls(FORGE_MAVEN_URL, "net/minecraftforge/forge.jar"),
+            vec!["https://forge.example.com/net/minecraftforge/forge.jar"]
+        );
+    }
+}
@@ -1,14 +1,42 @@
+use crate::launcher::install_log::InstallLog;
+use crate::launcher::processors::ProcessorPaths;
 use daedalus::minecraft::{ArgumentType, VersionInfo};
 use std::path::Path;
-use std::process::{Command, Stdio};
+use std::process::Stdio;
 use thiserror::Error;
 
 pub use crate::launcher::auth::provider::Credentials;
+pub use crate::launcher::download::{
+    download_from_mirrors, download_to_file, hash_file, DownloadContext, FileHashes, FileStatus,
+    InvalidFile, DEFAULT_CONCURRENT_DOWNLOADS,
+};
+pub use crate::launcher::http::{HttpSettings, DEFAULT_USER_AGENT};
+pub use crate::launcher::meta::{
+    fetch_metadata, LoaderVersionSelector, Metadata, MetadataCache, MetadataSource,
+    DEFAULT_METADATA_TTL, META_URL,
+};
+pub use crate::launcher::mirrors::{Mirrors, ASSETS_URL, FORGE_MAVEN_URL, LIBRARIES_URL};
+pub use crate::launcher::options::{LaunchOptions, QuickPlay, ServerOptions};
+pub use crate::launcher::platform::{get_arch, get_os, Arch, Platform};
+pub use crate::launcher::process::GameProcess;
+pub use crate::launcher::progress::{DownloadPhase, DownloadProgress, ProgressReporter};
+pub use crate::launcher::retry::RetryPolicy;
+pub use crate::launcher::rules::RuleContext;
 
 mod args;
 mod auth;
 mod download;
+mod http;
+mod install_log;
 mod java;
+mod meta;
+mod mirrors;
+mod options;
+mod platform;
+mod process;
+mod processors;
+mod progress;
+mod retry;
 mod rules;
 
 #[derive(Error, Debug)]
@@ -21,6 +49,20 @@ pub enum LauncherError {
     },
     #[error("Failed to run processor: {0}")]
     ProcessorError(String),
+    /// A processor exited unsuccessfully
+    #[error("Processor {jar} failed with {}: {stderr}", .exit_code.map(|x| format!("exit code {}", x)).unwrap_or_else(|| "no exit code".to_string()))]
+    ProcessorFailed {
+        /// The Maven artifact of the processor
+        jar: String,
+        /// The resolved arguments the processor ran with
+        args: Vec<String>,
+        /// The exit code of the processor, or `None` if it was killed by a signal
+        exit_code: Option<i32>,
+        /// Everything the processor wrote to its standard output
+        stdout: String,
+        /// Everything the processor wrote to its standard error
+        stderr: String,
+    },
     #[error("Invalid input: {0}")]
     InvalidInput(String),
     #[error("Error while managing asynchronous tasks")]
@@ -32,44 +74,81 @@ pub enum LauncherError {
         inner: std::io::Error,
         process: String,
     },
+    /// An archive couldn't be read
+    #[error("Error while extracting archive {path}: {inner}")]
+    ArchiveError {
+        /// The error of the archive reader
+        inner: zip::result::ZipError,
+        /// The path of the archive
+        path: String,
+    },
     #[error("Error while deserializing JSON")]
     SerdeError(#[from] serde_json::Error),
     #[error("Unable to fetch {item}")]
     FetchError { inner: reqwest::Error, item: String },
+    /// A server responded with an unsuccessful status
+    #[error("Server responded with status {status} for {url}")]
+    HttpError {
+        /// The unsuccessful status of the response
+        status: reqwest::StatusCode,
+        /// The URL which was requested
+        url: String,
+    },
+    /// A file couldn't be downloaded from any of its mirrors
+    #[error("Failed to download {path} from every mirror: {}", .errors.iter().map(|x| x.to_string()).collect::<Vec<_>>().join("; "))]
+    MirrorsExhausted {
+        /// The path the file was downloaded to
+        path: String,
+        /// The error of every mirror, in the order they were tried
+        errors: Vec<LauncherError>,
+    },
+    /// A manifest couldn't be fetched and the cache has no copy of it
+    #[error("Unable to fetch {item} and no cached copy is available: {inner}")]
+    MetadataUnavailable {
+        /// Why the manifest couldn't be fetched
+        inner: Box<LauncherError>,
+        /// The URL of the manifest
+        item: String,
+    },
+    /// A request failed on every attempt the retry policy allows
+    #[error("Failed to fetch {url} after {} attempts", .errors.len())]
+    RetriesExhausted {
+        /// The URL which was requested
+        url: String,
+        /// The error of every attempt, in order
+        errors: Vec<LauncherError>,
+    },
+    /// No version of a mod loader matches the requested one
+    #[error("Version {requested} of mod loader {loader} does not exist for {game_version}, available versions: {}", .available.join(", "))]
+    LoaderVersionNotFound {
+        /// The name of the mod loader
+        loader: String,
+        /// The game version the loader version was looked up for
+        game_version: String,
+        /// The exact version or the policy which was requested
+        requested: String,
+        /// The IDs of the versions the manifest lists for the game version
+        available: Vec<String>,
+    },
     #[error("{0}")]
     ParseError(String),
     #[error("Error while fetching metadata: {0}")]
     DaedalusError(#[from] daedalus::Error),
 }
 
-const META_URL: &str = "https://staging-cdn.modrinth.com/gamedata";
-
-pub async fn fetch_metadata() -> Result<
-    (
-        daedalus::minecraft::VersionManifest,
-        daedalus::modded::Manifest,
-        daedalus::modded::Manifest,
-    ),
-    LauncherError,
-> {
-    let (game, forge, fabric) = futures::future::join3(
-        daedalus::minecraft::fetch_version_manifest(Some(&*format!(
-            "{}/minecraft/v0/manifest.json",
-            META_URL
-        ))),
-        daedalus::modded::fetch_manifest(&*format!("{}/forge/v0/manifest.json", META_URL)),
-        daedalus::modded::fetch_manifest(&*format!("{}/fabric/v0/manifest.json", META_URL)),
-    )
-    .await;
-
-    Ok((game?, forge?, fabric?))
-}
-
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
+/// The mod loader a version is installed with
 pub enum ModLoader {
+    /// The unmodded game
     Vanilla,
+    /// Minecraft Forge
     Forge,
+    /// The Fabric loader
     Fabric,
+    /// The Quilt loader
+    Quilt,
+    /// NeoForge, the fork of Forge
+    NeoForge,
 }
 
 impl Default for ModLoader {
@@ -78,187 +157,267 @@ impl Default for ModLoader {
     }
 }
 
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+/// Whether a version is installed to play the game or to host a dedicated server
+pub enum Side {
+    /// The game client
+    #[default]
+    Client,
+    /// The dedicated server
+    Server,
+}
+
+impl Side {
+    /// The name of the side used by processors and their data
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Side::Client => "client",
+            Side::Server => "server",
+        }
+    }
+}
+
+/// Installs a version if needed and starts it, returning once the game is running
+#[allow(clippy::too_many_arguments)]
 pub async fn launch_minecraft(
     version_name: &str,
     mod_loader: Option<ModLoader>,
+    loader_version: &LoaderVersionSelector,
     root_dir: &Path,
     credentials: &Credentials,
-) -> Result<(), LauncherError> {
-    let (game, forge, fabric) = fetch_metadata().await?;
+    options: &LaunchOptions,
+    metadata_source: &MetadataSource,
+    context: &DownloadContext,
+) -> Result<GameProcess, LauncherError> {
+    let version_id = install_version(
+        version_name,
+        mod_loader,
+        loader_version,
+        Side::Client,
+        root_dir,
+        options.java_path.as_deref(),
+        metadata_source,
+        context,
+    )
+    .await?;
+
+    launch_installed(&version_id, root_dir, credentials, options).await
+}
+
+/// Installs the client or dedicated server of a version: saves its metadata, downloads its files
+/// and runs the processors of its mod loader with the given Java executable, or `java` from the
+/// `PATH`. Returns the ID of the installed version, which [`launch_installed`] or
+/// [`launch_server`] starts.
+///
+/// Every installation is logged to the `logs/installs` folder of the root directory, including
+/// the output of the processors.
+#[allow(clippy::too_many_arguments)]
+pub async fn install_version(
+    version_name: &str,
+    mod_loader: Option<ModLoader>,
+    loader_version: &LoaderVersionSelector,
+    side: Side,
+    root_dir: &Path,
+    java_path: Option<&Path>,
+    metadata_source: &MetadataSource,
+    context: &DownloadContext,
+) -> Result<String, LauncherError> {
+    let mut log = InstallLog::create(root_dir, version_name);
+    log.write(format!(
+        "Installing the {} of {} with mod loader {:?} ({:?})",
+        side.as_str(),
+        version_name,
+        mod_loader.unwrap_or_default(),
+        loader_version
+    ));
+
+    let result = install(
+        version_name,
+        mod_loader,
+        loader_version,
+        side,
+        root_dir,
+        java_path,
+        metadata_source,
+        context,
+        &mut log,
+    )
+    .await;
+
+    match &result {
+        Ok(version_id) => log.write(format!("Installed version {}", version_id)),
+        Err(err) => log.write(format!("Installation failed: {}", err)),
+    }
+
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn install(
+    version_name: &str,
+    mod_loader: Option<ModLoader>,
+    loader_version: &LoaderVersionSelector,
+    side: Side,
+    root_dir: &Path,
+    java_path: Option<&Path>,
+    metadata_source: &MetadataSource,
+    context: &DownloadContext,
+    log: &mut InstallLog,
+) -> Result<String, LauncherError> {
+    // Only the manifests this installation needs are fetched, so a source missing the manifest
+    // of another loader doesn't get in the way
+    let cache = MetadataCache::new(root_dir);
+    let (versions, loader_manifest) = futures::future::join(
+        meta::fetch_version_manifest(context, metadata_source, Some(&cache)),
+        meta::fetch_loader_manifest(
+            context,
+            metadata_source,
+            Some(&cache),
+            mod_loader.unwrap_or_default(),
+        ),
+    )
+    .await;
+    let (versions, loader_manifest) = (versions?, loader_manifest?);
 
     let versions_path = crate::util::absolute_path(root_dir.join("versions"))?;
     let libraries_path = crate::util::absolute_path(root_dir.join("libraries"))?;
     let assets_path = crate::util::absolute_path(root_dir.join("assets"))?;
     let legacy_assets_path = crate::util::absolute_path(root_dir.join("resources"))?;
 
-    let mut version = download::download_version_info(
+    let loader_version = match &loader_manifest {
+        Some(manifest) => Some(
+            meta::resolve_loader_version(
+                context,
+                manifest,
+                mod_loader.unwrap_or_default(),
+                version_name,
+                loader_version,
+            )
+            .await?,
+        ),
+        None => None,
+    };
+
+    let version = download::download_version_info(
         &versions_path,
-        game.versions
+        versions
+            .versions
             .iter()
             .find(|x| x.id == version_name)
             .ok_or_else(|| {
                 LauncherError::InvalidInput(format!("Version {} does not exist", version_name))
             })?,
-        match mod_loader.unwrap_or_default() {
-            ModLoader::Vanilla => None,
-            ModLoader::Forge | ModLoader::Fabric => {
-                let loaders = if mod_loader.unwrap_or_default() == ModLoader::Forge {
-                    &forge
-                        .game_versions
-                        .iter()
-                        .find(|x| x.id == version_name)
-                        .ok_or_else(|| {
-                            LauncherError::InvalidInput(format!(
-                                "Version {} for mod loader Forge does not exist",
-                                version_name
-                            ))
-                        })?
-                        .loaders
-                } else {
-                    &fabric
-                        .game_versions
-                        .iter()
-                        .find(|x| x.id == version_name)
-                        .ok_or_else(|| {
-                            LauncherError::InvalidInput(format!(
-                                "Version {} for mod loader Fabric does not exist",
-                                version_name
-                            ))
-                        })?
-                        .loaders
-                };
-
-                let loader = if let Some(version) =
-                    loaders.get(&daedalus::modded::LoaderType::Stable)
-                {
-                    Some(version.clone())
-                } else if let Some(version) = loaders.get(&daedalus::modded::LoaderType::Latest) {
-                    Some(version.clone())
-                } else {
-                    None
-                };
-
-                Some(loader.ok_or_else(|| {
-                    LauncherError::InvalidInput(format!(
-                        "No mod loader version found for version {}",
-                        version_name
-                    ))
-                })?)
-            }
-        }
-        .as_ref(),
+        loader_version.as_ref(),
+        context,
     )
     .await?;
 
-    let client_path = crate::util::absolute_path(
-        root_dir
-            .join("versions")
-            .join(&version.id)
-            .join(format!("{}.jar", &version.id)),
-    )?;
-    let natives_path = crate::util::absolute_path(root_dir.join("natives").join(&version.id))?;
+    log.write(format!("Resolved version {}", version.id));
+
+    let client_path = versions_path
+        .join(&version.id)
+        .join(format!("{}.jar", &version.id));
+    let server_path = download::get_server_path(&versions_path, &version.id);
 
-    download_minecraft(
+    match side {
+        Side::Client => {
+            let natives_path =
+                crate::util::absolute_path(root_dir.join("natives").join(&version.id))?;
+
+            download_minecraft(
+                &version,
+                &versions_path,
+                &assets_path,
+                &legacy_assets_path,
+                &libraries_path,
+                &natives_path,
+                &Platform::host(),
+                context,
+            )
+            .await?;
+        }
+        Side::Server => {
+            download_server(
+                &version,
+                &versions_path,
+                &libraries_path,
+                &Platform::host(),
+                context,
+            )
+            .await?;
+        }
+    }
+    log.write("Downloaded all files");
+
+    processors::run_processors(
         &version,
-        &versions_path,
-        &assets_path,
-        &legacy_assets_path,
-        &libraries_path,
-        &natives_path,
+        side,
+        version_name,
+        &ProcessorPaths {
+            root: crate::util::absolute_path(root_dir)?,
+            libraries: libraries_path,
+            client_jar: client_path,
+            server_jar: server_path,
+            state: versions_path
+                .join(&version.id)
+                .join(format!("{}.processors.json", &version.id)),
+        },
+        java_path.unwrap_or_else(|| Path::new("java")),
+        log,
     )
     .await?;
 
-    if let Some(processors) = &version.processors {
-        if let Some(ref mut data) = version.data {
-            data.insert(
-                "SIDE".to_string(),
-                daedalus::modded::SidedDataEntry {
-                    client: "client".to_string(),
-                    server: "".to_string(),
-                },
-            );
-            data.insert(
-                "MINECRAFT_JAR".to_string(),
-                daedalus::modded::SidedDataEntry {
-                    client: client_path.to_string_lossy().to_string(),
-                    server: "".to_string(),
-                },
-            );
-            data.insert(
-                "MINECRAFT_VERSION".to_string(),
-                daedalus::modded::SidedDataEntry {
-                    client: version_name.to_string(),
-                    server: "".to_string(),
-                },
-            );
-            data.insert(
-                "ROOT".to_string(),
-                daedalus::modded::SidedDataEntry {
-                    client: root_dir.to_string_lossy().to_string(),
-                    server: "".to_string(),
-                },
-            );
-            data.insert(
-                "LIBRARY_DIR".to_string(),
-                daedalus::modded::SidedDataEntry {
-                    client: libraries_path.to_string_lossy().to_string(),
-                    server: "".to_string(),
-                },
-            );
+    Ok(version.id)
+}
 
-            for processor in processors {
-                if let Some(sides) = &processor.sides {
-                    if !sides.contains(&"client".to_string()) {
-                        continue;
-                    }
-                }
-
-                let mut cp = processor.classpath.clone();
-                cp.push(processor.jar.clone());
-
-                let child = Command::new("java")
-                    .arg("-cp")
-                    .arg(args::get_class_paths_jar(&libraries_path, &cp)?)
-                    .arg(
-                        args::get_processor_main_class(args::get_lib_path(
-                            &libraries_path,
-                            &processor.jar,
-                        )?)
-                        .await?
-                        .ok_or_else(|| {
-                            LauncherError::ProcessorError(format!(
-                                "Could not find processor main class for {}",
-                                processor.jar
-                            ))
-                        })?,
-                    )
-                    .args(args::get_processor_arguments(
-                        &libraries_path,
-                        &processor.args,
-                        data,
-                    )?)
-                    .output()
-                    .map_err(|err| LauncherError::ProcessError {
-                        inner: err,
-                        process: "java".to_string(),
-                    })?;
-
-                if !child.status.success() {
-                    return Err(LauncherError::ProcessorError(
-                        String::from_utf8_lossy(&*child.stderr).to_string(),
-                    ));
-                }
-            }
-        }
+/// Starts a version installed by [`install_version`], without any network access
+pub async fn launch_installed(
+    version_id: &str,
+    root_dir: &Path,
+    credentials: &Credentials,
+    options: &LaunchOptions,
+) -> Result<GameProcess, LauncherError> {
+    let rule_context = options.get_rule_context();
+
+    let libraries_path = crate::util::absolute_path(root_dir.join("libraries"))?;
+    let assets_path = crate::util::absolute_path(root_dir.join("assets"))?;
+    let versions_path = crate::util::absolute_path(root_dir.join("versions"))?;
+    let client_path = versions_path
+        .join(version_id)
+        .join(format!("{}.jar", version_id));
+    let natives_path = crate::util::absolute_path(root_dir.join("natives").join(version_id))?;
+
+    let version = read_installed_version(&versions_path, version_id).await?;
+
+    if !client_path.exists() {
+        return Err(LauncherError::InvalidInput(format!(
+            "The client of version {} is not installed",
+            version_id
+        )));
     }
 
+    let game_dir = match &options.working_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            crate::util::absolute_path(dir)?
+        }
+        None => root_dir.to_path_buf(),
+    };
     let arguments = version.arguments.unwrap_or_default();
 
-    let mut child = Command::new("java")
+    let child = tokio::process::Command::new(options.get_java_path())
         .args(args::get_jvm_arguments(
             arguments.get(&ArgumentType::Jvm).map(|x| x.as_slice()),
             &natives_path,
-            &*args::get_class_paths(&libraries_path, version.libraries.as_slice(), &client_path)?,
+            &libraries_path,
+            &*args::get_class_paths(
+                &libraries_path,
+                version.libraries.as_slice(),
+                &client_path,
+                &rule_context,
+            )?,
+            version_id,
+            options,
         )?)
         .arg(version.main_class)
         .args(args::get_minecraft_arguments(
@@ -267,27 +426,168 @@ pub async fn launch_minecraft(
             credentials,
             &*version.id,
             &version.asset_index.id,
-            root_dir,
+            &game_dir,
             &assets_path,
             &version.type_,
+            options,
         )?)
-        .current_dir(root_dir)
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
+        .envs(options.env.iter().map(|(key, value)| (key, value)))
+        .current_dir(&game_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
         .spawn()
         .map_err(|err| LauncherError::ProcessError {
             inner: err,
             process: "minecraft".to_string(),
         })?;
 
-    child.wait().map_err(|err| LauncherError::ProcessError {
-        inner: err,
-        process: "minecraft".to_string(),
-    })?;
+    Ok(GameProcess::new(child))
+}
 
-    Ok(())
+/// Starts the dedicated server of a version installed by [`install_version`] in `server_dir`,
+/// without any network access
+pub async fn launch_server(
+    version_id: &str,
+    root_dir: &Path,
+    server_dir: &Path,
+    options: &LaunchOptions,
+    server_options: &ServerOptions,
+) -> Result<GameProcess, LauncherError> {
+    let libraries_path = crate::util::absolute_path(root_dir.join("libraries"))?;
+    let versions_path = crate::util::absolute_path(root_dir.join("versions"))?;
+    let server_path = download::get_server_path(&versions_path, version_id);
+
+    let version = read_installed_version(&versions_path, version_id).await?;
+
+    if !server_path.exists() {
+        return Err(LauncherError::InvalidInput(format!(
+            "The server of version {} is not installed",
+            version_id
+        )));
+    }
+    std::fs::create_dir_all(server_dir)?;
+    let server_dir = crate::util::absolute_path(server_dir)?;
+    server_options.write_files(&server_dir)?;
+
+    let rule_context = options.get_rule_context();
+    let mut command = tokio::process::Command::new(options.get_java_path());
+
+    if version.processors.is_some() {
+        // Forge and NeoForge start servers through the same bootstrap as their clients, with the
+        // launch target of the server. Since 1.17 the bootstrap finds the patched server jar in
+        // the libraries by itself, while older versions need it on the class path
+        let class_paths = if version.main_class == "cpw.mods.bootstraplauncher.BootstrapLauncher" {
+            args::get_library_class_paths(
+                &libraries_path,
+                version.libraries.as_slice(),
+                &rule_context,
+            )?
+        } else {
+            args::get_class_paths(
+                &libraries_path,
+                version.libraries.as_slice(),
+                &server_path,
+                &rule_context,
+            )?
+        };
+        let arguments = version.arguments.unwrap_or_default();
+
+        command
+            .args(args::get_jvm_arguments(
+                arguments.get(&ArgumentType::Jvm).map(|x| x.as_slice()),
+                &root_dir.join("natives").join(version_id),
+                &libraries_path,
+                &class_paths,
+                version_id,
+                options,
+            )?)
+            .arg(&version.main_class)
+            .args(
+                args::get_server_loader_arguments(
+                    arguments.get(&ArgumentType::Game).map(|x| x.as_slice()),
+                )
+                .ok_or_else(|| {
+                    LauncherError::InvalidInput(format!(
+                        "The mod loader of version {} has no server launch target",
+                        version_id
+                    ))
+                })?,
+            );
+    } else if let Some(package) = version.main_class.strip_suffix(".KnotClient") {
+        // Fabric and Quilt start servers through their own entrypoint, which finds the server jar
+        // on the class path
+        command
+            .args(args::get_custom_jvm_arguments(options))
+            .arg("-cp")
+            .arg(args::get_class_paths(
+                &libraries_path,
+                version.libraries.as_slice(),
+                &server_path,
+                &rule_context,
+            )?)
+            .arg(format!("{}.KnotServer", package));
+    } else if let Some(main_class) =
+        args::get_legacy_server_main_class(version.minecraft_arguments.as_deref())
+    {
+        // Forge servers before 1.13 wrap the server jar with the launch wrapper of Forge
+        command
+            .args(args::get_custom_jvm_arguments(options))
+            .arg("-cp")
+            .arg(args::get_class_paths(
+                &libraries_path,
+                version.libraries.as_slice(),
+                &server_path,
+                &rule_context,
+            )?)
+            .arg(main_class);
+    } else {
+        command
+            .args(args::get_custom_jvm_arguments(options))
+            .arg("-jar")
+            .arg(&server_path);
+    }
+
+    let child = command
+        .arg("nogui")
+        .args(&options.game_arguments)
+        .envs(options.env.iter().map(|(key, value)| (key, value)))
+        .current_dir(&server_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| LauncherError::ProcessError {
+            inner: err,
+            process: "minecraft server".to_string(),
+        })?;
+
+    Ok(GameProcess::new(child))
+}
+
+async fn read_installed_version(
+    versions_path: &Path,
+    version_id: &str,
+) -> Result<VersionInfo, LauncherError> {
+    let path = versions_path
+        .join(version_id)
+        .join(format!("{}.json", version_id));
+
+    if !path.exists() {
+        return Err(LauncherError::InvalidInput(format!(
+            "Version {} is not installed",
+            version_id
+        )));
+    }
+
+    Ok(serde_json::from_str(
+        &tokio::fs::read_to_string(&path).await?,
+    )?)
 }
 
+/// Downloads everything a version needs to run on a platform. The platform doesn't need to be
+/// the one the launcher is running on, so installations can be prepared for other machines
+#[allow(clippy::too_many_arguments)]
 pub async fn download_minecraft(
     version: &VersionInfo,
     versions_dir: &Path,
@@ -295,11 +595,13 @@ pub async fn download_minecraft(
     legacy_assets_dir: &Path,
     libraries_dir: &Path,
     natives_dir: &Path,
+    platform: &Platform,
+    context: &DownloadContext,
 ) -> Result<(), LauncherError> {
-    let assets_index = download::download_assets_index(assets_dir, version).await?;
+    let assets_index = download::download_assets_index(assets_dir, version, context).await?;
 
     let (a, b, c) = futures::future::join3(
-        download::download_client(versions_dir, version),
+        download::download_client(versions_dir, version, context),
         download::download_assets(
             assets_dir,
             if version.assets == "legacy" {
@@ -308,8 +610,15 @@ pub async fn download_minecraft(
                 None
             },
             &assets_index,
+            context,
+        ),
+        download::download_libraries(
+            libraries_dir,
+            Some(natives_dir),
+            version.libraries.as_slice(),
+            platform,
+            context,
         ),
-        download::download_libraries(libraries_dir, natives_dir, version.libraries.as_slice()),
     )
     .await;
 
@@ -319,3 +628,47 @@ pub async fn download_minecraft(
 
     Ok(())
 }
+
+/// Downloads the dedicated server jar of a version and the libraries it needs on a platform
+pub async fn download_server(
+    version: &VersionInfo,
+    versions_dir: &Path,
+    libraries_dir: &Path,
+    platform: &Platform,
+    context: &DownloadContext,
+) -> Result<(), LauncherError> {
+    let (a, b) = futures::future::join(
+        download::download_server(versions_dir, version, context),
+        download::download_libraries(
+            libraries_dir,
+            None,
+            version.libraries.as_slice(),
+            platform,
+            context,
+        ),
+    )
+    .await;
+
+    a?;
+    b?;
+
+    Ok(())
+}
+
+/// Checks the client, assets and libraries of an installed version against their known hashes,
+/// returning every file of the given platform which is missing or corrupt
+pub async fn verify_installation(
+    version: &VersionInfo,
+    versions_dir: &Path,
+    assets_dir: &Path,
+    libraries_dir: &Path,
+    platform: &Platform,
+) -> Result<Vec<InvalidFile>, LauncherError> {
+    let mut invalid = download::verify_client(versions_dir, version).await?;
+    invalid.extend(download::verify_assets(assets_dir, version).await?);
+    invalid.extend(
+        download::verify_libraries(libraries_dir, version.libraries.as_slice(), platform).await?,
+    );
+
+    Ok(invalid)
+}
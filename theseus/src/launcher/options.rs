@@ -0,0 +1,288 @@
+use crate::launcher::download::save_file;
+use crate::launcher::rules::RuleContext;
+use crate::launcher::LauncherError;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+/// A world, server or Realm the game joins as soon as it starts. Only versions supporting quick
+/// play honor it
+pub enum QuickPlay {
+    /// The name of the singleplayer world to load
+    Singleplayer(String),
+    /// The address of the multiplayer server to join
+    Multiplayer(String),
+    /// The ID of the Realm to join
+    Realms(String),
+}
+
+#[derive(Debug, Clone, Default)]
+/// How the game is started
+pub struct LaunchOptions {
+    /// The maximum heap size of the JVM in megabytes, passed as `-Xmx`
+    pub max_memory: Option<u32>,
+    /// The initial heap size of the JVM in megabytes, passed as `-Xms`
+    pub min_memory: Option<u32>,
+    /// Extra arguments passed to the JVM, before the ones of the version
+    pub jvm_arguments: Vec<String>,
+    /// Extra arguments passed to the game, after the ones of the version
+    pub game_arguments: Vec<String>,
+    /// The width and height of the game window
+    pub resolution: Option<(u32, u32)>,
+    /// Whether the game starts in fullscreen
+    pub fullscreen: bool,
+    /// Whether the game starts in demo mode
+    pub demo: bool,
+    /// What the game joins once it started
+    pub quick_play: Option<QuickPlay>,
+    /// The Java executable the game runs with. Defaults to `java` from the `PATH`
+    pub java_path: Option<PathBuf>,
+    /// Environment variables set for the game, on top of the ones of the launcher
+    pub env: Vec<(String, String)>,
+    /// The directory the game runs in, keeping its saves, settings and mods. Defaults to the
+    /// root directory of the launcher
+    pub working_dir: Option<PathBuf>,
+    /// The platform and launcher features rules of libraries and arguments are evaluated
+    /// against. Defaults to the host platform. Features these options enable, like a custom
+    /// resolution or quick play, are always turned on
+    pub rule_context: Option<RuleContext>,
+}
+
+impl LaunchOptions {
+    /// Sets the maximum heap size of the JVM in megabytes
+    pub fn max_memory(mut self, megabytes: u32) -> Self {
+        self.max_memory = Some(megabytes);
+        self
+    }
+
+    /// Sets the initial heap size of the JVM in megabytes
+    pub fn min_memory(mut self, megabytes: u32) -> Self {
+        self.min_memory = Some(megabytes);
+        self
+    }
+
+    /// Adds an extra argument passed to the JVM
+    pub fn jvm_argument(mut self, argument: impl Into<String>) -> Self {
+        self.jvm_arguments.push(argument.into());
+        self
+    }
+
+    /// Adds an extra argument passed to the game
+    pub fn game_argument(mut self, argument: impl Into<String>) -> Self {
+        self.game_arguments.push(argument.into());
+        self
+    }
+
+    /// Sets the width and height of the game window
+    pub fn resolution(mut self, width: u32, height: u32) -> Self {
+        self.resolution = Some((width, height));
+        self
+    }
+
+    /// Sets whether the game starts in fullscreen
+    pub fn fullscreen(mut self, fullscreen: bool) -> Self {
+        self.fullscreen = fullscreen;
+        self
+    }
+
+    /// Sets whether the game starts in demo mode
+    pub fn demo(mut self, demo: bool) -> Self {
+        self.demo = demo;
+        self
+    }
+
+    /// Sets what the game joins once it started
+    pub fn quick_play(mut self, quick_play: QuickPlay) -> Self {
+        self.quick_play = Some(quick_play);
+        self
+    }
+
+    /// Sets the Java executable the game runs with
+    pub fn java_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.java_path = Some(path.into());
+        self
+    }
+
+    /// Sets an environment variable for the game
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Sets the directory the game runs in
+    pub fn working_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.working_dir = Some(path.into());
+        self
+    }
+
+    /// Sets the platform and launcher features rules are evaluated against
+    pub fn rule_context(mut self, context: RuleContext) -> Self {
+        self.rule_context = Some(context);
+        self
+    }
+
+    /// The Java executable the game runs with
+    pub(crate) fn get_java_path(&self) -> PathBuf {
+        self.java_path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("java"))
+    }
+
+    /// The rule context of these options, with the features they enable turned on
+    pub(crate) fn get_rule_context(&self) -> RuleContext {
+        let mut context = self.rule_context.clone().unwrap_or_else(RuleContext::host);
+
+        context.has_custom_resolution |= self.resolution.is_some();
+        context.is_demo_user |= self.demo;
+        context.is_quick_play_singleplayer |=
+            matches!(self.quick_play, Some(QuickPlay::Singleplayer(_)));
+        context.is_quick_play_multiplayer |=
+            matches!(self.quick_play, Some(QuickPlay::Multiplayer(_)));
+        context.is_quick_play_realms |= matches!(self.quick_play, Some(QuickPlay::Realms(_)));
+
+        context
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// How the directory of a dedicated server is set up before it starts
+pub struct ServerOptions {
+    /// Whether the Minecraft EULA is accepted, which the server requires to start. This must only
+    /// be set once the user agreed to the EULA
+    pub accept_eula: bool,
+    /// Entries written to `server.properties`, replacing the existing values of the same keys
+    pub properties: Vec<(String, String)>,
+}
+
+impl ServerOptions {
+    /// Sets whether the Minecraft EULA is accepted
+    pub fn accept_eula(mut self, accept: bool) -> Self {
+        self.accept_eula = accept;
+        self
+    }
+
+    /// Sets an entry of `server.properties`
+    pub fn property(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.properties.push((key.into(), value.into()));
+        self
+    }
+
+    /// Writes `eula.txt` and `server.properties` to the server directory. Properties which
+    /// aren't set by the options are kept as they are
+    pub(crate) fn write_files(&self, server_dir: &Path) -> Result<(), LauncherError> {
+        if self.accept_eula {
+            save_file(
+                &server_dir.join("eula.txt"),
+                &bytes::Bytes::from("eula=true\n"),
+            )?;
+        }
+
+        if self.properties.is_empty() {
+            return Ok(());
+        }
+
+        let path = server_dir.join("server.properties");
+        let existing = match std::fs::read_to_string(&path) {
+            Ok(existing) => existing,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut remaining = self.properties.iter().collect::<Vec<_>>();
+        let mut lines = Vec::new();
+
+        for line in existing.lines() {
+            let key = line.split('=').next().unwrap_or_default().trim();
+
+            if !line.trim_start().starts_with('#') {
+                if let Some(index) = remaining.iter().position(|(x, _)| x == key) {
+                    let (key, value) = remaining.remove(index);
+                    lines.push(format!("{}={}", key, value));
+                    continue;
+                }
+            }
+
+            lines.push(line.to_string());
+        }
+        lines.extend(
+            remaining
+                .into_iter()
+                .map(|(key, value)| format!("{}={}", key, value)),
+        );
+
+        save_file(&path, &bytes::Bytes::from(lines.join("\n") + "\n"))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("theseus-server-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn rule_context_keeps_overrides_and_adds_options() {
+        let mut base = RuleContext::host();
+        base.has_quick_plays_support = true;
+
+        let context = LaunchOptions::default()
+            .rule_context(base)
+            .resolution(854, 480)
+            .quick_play(QuickPlay::Multiplayer("localhost".to_string()))
+            .get_rule_context();
+
+        assert!(context.has_quick_plays_support);
+        assert!(context.has_custom_resolution);
+        assert!(context.is_quick_play_multiplayer);
+        assert!(!context.is_quick_play_singleplayer);
+        assert!(!context.is_demo_user);
+    }
+
+    #[test]
+    fn writes_eula_only_once_accepted() {
+        let dir = server_dir();
+
+        ServerOptions::default().write_files(&dir).unwrap();
+        assert!(!dir.join("eula.txt").exists());
+        assert!(!dir.join("server.properties").exists());
+
+        ServerOptions::default()
+            .accept_eula(true)
+            .write_files(&dir)
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("eula.txt")).unwrap(),
+            "eula=true\n"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replaces_and_appends_properties() {
+        let dir = server_dir();
+        std::fs::write(
+            dir.join("server.properties"),
+            "#Minecraft server properties\nmotd=A Minecraft Server\nserver-port=25565\n",
+        )
+        .unwrap();
+
+        ServerOptions::default()
+            .property("server-port", "25566")
+            .property("online-mode", "false")
+            .write_files(&dir)
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dir.join("server.properties")).unwrap(),
+            "#Minecraft server properties\nmotd=A Minecraft Server\nserver-port=25566\nonline-mode=false\n"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+use daedalus::minecraft::Os;
+use lazy_static::lazy_static;
+
+lazy_static! {
+    static ref HOST_PLATFORM: Platform = Platform {
+        os: get_os(),
+        arch: get_arch(),
+        os_version: sys_info::os_release().unwrap_or_default(),
+    };
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+/// A snapshot of the platform the game runs on, which OS rules are evaluated against. Building
+/// one by hand evaluates rules as if the launcher ran on that platform
+pub struct Platform {
+    /// The operating system
+    pub os: Os,
+    /// The processor architecture
+    pub arch: Arch,
+    /// The release of the operating system, which OS rules match against with a regex
+    pub os_version: String,
+}
+
+impl Platform {
+    /// The platform the launcher is running on. It is only looked up once, so every call
+    /// returns the same snapshot
+    pub fn host() -> Self {
+        HOST_PLATFORM.clone()
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
+/// A processor architecture, as named by the OS rules of version manifests
+pub enum Arch {
+    /// 32-bit x86
+    X86,
+    /// 64-bit x86
+    X86_64,
+    /// 32-bit ARM
+    Arm32,
+    /// 64-bit ARM
+    Arm64,
+    /// An architecture the launcher doesn't know about
+    Unknown,
+}
+
+impl Arch {
+    /// Parses an architecture name used by OS rules. Returns `None` for unknown names
+    pub fn from_name(name: &str) -> Option<Arch> {
+        match name {
+            "x86" | "i386" | "i686" => Some(Arch::X86),
+            "x86_64" | "amd64" => Some(Arch::X86_64),
+            "arm" | "arm32" => Some(Arch::Arm32),
+            "arm64" | "aarch64" => Some(Arch::Arm64),
+            _ => None,
+        }
+    }
+
+    /// The pointer width of the architecture, which replaces `${arch}` in natives classifiers
+    pub fn bits(&self) -> &'static str {
+        match self {
+            Arch::X86 | Arch::Arm32 => "32",
+            Arch::X86_64 | Arch::Arm64 | Arch::Unknown => "64",
+        }
+    }
+
+    /// The suffix of natives classifiers built for this architecture specifically, such as
+    /// `natives-linux-arm64`
+    pub fn classifier_suffix(&self) -> Option<&'static str> {
+        match self {
+            Arch::Arm32 => Some("arm32"),
+            Arch::Arm64 => Some("arm64"),
+            Arch::X86 | Arch::X86_64 | Arch::Unknown => None,
+        }
+    }
+}
+
+/// Gets the operating system the launcher is running on
+pub fn get_os() -> Os {
+    match std::env::consts::OS {
+        "windows" => Os::Windows,
+        "macos" => Os::Osx,
+        "linux" => Os::Linux,
+        _ => Os::Unknown,
+    }
+}
+
+/// Gets the architecture the launcher is running on
+pub fn get_arch() -> Arch {
+    match std::env::consts::ARCH {
+        "x86" => Arch::X86,
+        "x86_64" => Arch::X86_64,
+        "arm" => Arch::Arm32,
+        "aarch64" => Arch::Arm64,
+        _ => Arch::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arch_from_name() {
+        assert_eq!(Arch::from_name("x86"), Some(Arch::X86));
+        assert_eq!(Arch::from_name("i686"), Some(Arch::X86));
+        assert_eq!(Arch::from_name("amd64"), Some(Arch::X86_64));
+        assert_eq!(Arch::from_name("arm32"), Some(Arch::Arm32));
+        assert_eq!(Arch::from_name("aarch64"), Some(Arch::Arm64));
+        assert_eq!(Arch::from_name("riscv64"), None);
+    }
+
+    #[test]
+    fn arch_classifier_suffix() {
+        assert_eq!(Arch::Arm32.classifier_suffix(), Some("arm32"));
+        assert_eq!(Arch::Arm64.classifier_suffix(), Some("arm64"));
+        assert_eq!(Arch::X86.classifier_suffix(), None);
+        assert_eq!(Arch::X86_64.classifier_suffix(), None);
+        assert_eq!(Arch::Unknown.classifier_suffix(), None);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::launcher::LauncherError;
+use std::process::ExitStatus;
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout};
+
+#[derive(Debug)]
+/// A running game. Its output is piped to the launcher, so the streams should be read, or the
+/// game stalls once the pipes are full. Dropping the handle leaves the game running
+pub struct GameProcess {
+    child: Child,
+}
+
+impl GameProcess {
+    pub(crate) fn new(child: Child) -> Self {
+        GameProcess { child }
+    }
+
+    /// The process ID of the game, or `None` once the game exited and was waited on
+    pub fn pid(&self) -> Option<u32> {
+        self.child.id()
+    }
+
+    /// Waits for the game to exit
+    pub async fn wait(&mut self) -> Result<ExitStatus, LauncherError> {
+        self.child
+            .wait()
+            .await
+            .map_err(|err| LauncherError::ProcessError {
+                inner: err,
+                process: "minecraft".to_string(),
+            })
+    }
+
+    /// Kills the game and waits for it to exit
+    pub async fn kill(&mut self) -> Result<(), LauncherError> {
+        self.child
+            .kill()
+            .await
+            .map_err(|err| LauncherError::ProcessError {
+                inner: err,
+                process: "minecraft".to_string(),
+            })
+    }
+
+    /// Takes the standard input of the game, which dedicated servers read console commands
+    /// from. Returns `None` for clients or if it was taken before
+    pub fn stdin(&mut self) -> Option<ChildStdin> {
+        self.child.stdin.take()
+    }
+
+    /// Takes the standard output of the game. Returns `None` if it was taken before
+    pub fn stdout(&mut self) -> Option<ChildStdout> {
+        self.child.stdout.take()
+    }
+
+    /// Takes the standard error of the game. Returns `None` if it was taken before
+    pub fn stderr(&mut self) -> Option<ChildStderr> {
+        self.child.stderr.take()
+    }
+}
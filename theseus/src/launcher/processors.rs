@@ -0,0 +1,273 @@
+use crate::launcher::download::{hash_file, save_file};
+use crate::launcher::install_log::InstallLog;
+use crate::launcher::{args, LauncherError, Side};
+use daedalus::minecraft::VersionInfo;
+use daedalus::modded::{Processor, SidedDataEntry};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The files and directories processors read from and write to
+pub(crate) struct ProcessorPaths {
+    /// The root directory of the launcher
+    pub root: PathBuf,
+    /// The directory libraries are stored in
+    pub libraries: PathBuf,
+    /// The client jar of the version
+    pub client_jar: PathBuf,
+    /// The dedicated server jar of the version
+    pub server_jar: PathBuf,
+    /// The file recording which processors of the version completed
+    pub state: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+/// The processors of a version which completed, identified by their fingerprint
+struct ProcessorState {
+    completed: Vec<String>,
+}
+
+/// Runs the processors of a mod loader which apply to a side. Processors patch the game jar and
+/// generate the files the loader needs. Processors whose outputs are present with the expected
+/// hashes are skipped, as well as processors without outputs which completed before while the
+/// outputs of every other processor are still valid
+pub(crate) async fn run_processors(
+    version: &VersionInfo,
+    side: Side,
+    game_version: &str,
+    paths: &ProcessorPaths,
+    java_path: &Path,
+    log: &mut InstallLog,
+) -> Result<(), LauncherError> {
+    let processors = match &version.processors {
+        Some(processors) => processors,
+        None => return Ok(()),
+    };
+
+    let mut data = version
+        .data
+        .iter()
+        .flatten()
+        .map(|(key, entry)| {
+            (
+                key.clone(),
+                SidedDataEntry {
+                    client: entry.client.clone(),
+                    server: entry.server.clone(),
+                },
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    data.insert(
+        "SIDE".to_string(),
+        SidedDataEntry {
+            client: Side::Client.as_str().to_string(),
+            server: Side::Server.as_str().to_string(),
+        },
+    );
+    data.insert(
+        "MINECRAFT_JAR".to_string(),
+        SidedDataEntry {
+            client: paths.client_jar.to_string_lossy().to_string(),
+            server: paths.server_jar.to_string_lossy().to_string(),
+        },
+    );
+    data.insert("MINECRAFT_VERSION".to_string(), unsided(game_version));
+    data.insert("ROOT".to_string(), unsided(&paths.root.to_string_lossy()));
+    data.insert(
+        "LIBRARY_DIR".to_string(),
+        unsided(&paths.libraries.to_string_lossy()),
+    );
+
+    let mut state = read_state(&paths.state);
+    let mut pending = Vec::new();
+
+    for processor in processors {
+        if let Some(sides) = &processor.sides {
+            if !sides.iter().any(|x| x == side.as_str()) {
+                continue;
+            }
+        }
+
+        let outputs = get_outputs(processor, paths, &data, side)?;
+        let valid = !outputs.is_empty() && check_outputs(&outputs).await?.is_none();
+
+        pending.push((processor, outputs, valid));
+    }
+
+    // Processors without outputs generate files which later processors read, so once any
+    // output is missing or corrupt they all run again
+    if let Some((processor, _, _)) = pending
+        .iter()
+        .find(|(_, outputs, valid)| !outputs.is_empty() && !valid)
+    {
+        if !state.completed.is_empty() {
+            log.write(format!(
+                "Outputs of processor {} are invalid, running every processor again",
+                processor.jar
+            ));
+            state.completed.clear();
+        }
+    }
+
+    for (processor, outputs, valid) in pending {
+        let fingerprint = get_fingerprint(processor, side);
+
+        let completed = if outputs.is_empty() {
+            state.completed.contains(&fingerprint)
+        } else {
+            valid
+        };
+        if completed {
+            log.write(format!(
+                "Skipping processor {}, it completed before",
+                processor.jar
+            ));
+            continue;
+        }
+
+        let mut cp = processor.classpath.clone();
+        cp.push(processor.jar.clone());
+
+        let main_class =
+            args::get_processor_main_class(args::get_lib_path(&paths.libraries, &processor.jar)?)
+                .await?
+                .ok_or_else(|| {
+                    LauncherError::ProcessorError(format!(
+                        "Could not find processor main class for {}",
+                        processor.jar
+                    ))
+                })?;
+        let arguments =
+            args::get_processor_arguments(&paths.libraries, &processor.args, &data, side)?;
+
+        log.write(format!(
+            "Running processor {} ({}) with arguments {}",
+            processor.jar,
+            main_class,
+            arguments.join(" ")
+        ));
+
+        let output = tokio::process::Command::new(java_path)
+            .arg("-cp")
+            .arg(args::get_class_paths_jar(&paths.libraries, &cp)?)
+            .arg(&main_class)
+            .args(&arguments)
+            .output()
+            .await
+            .map_err(|err| LauncherError::ProcessError {
+                inner: err,
+                process: "java".to_string(),
+            })?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+
+        log.write(format!(
+            "Processor {} exited with {}",
+            processor.jar, output.status
+        ));
+        log.write_output("stdout", &stdout);
+        log.write_output("stderr", &stderr);
+
+        if !output.status.success() {
+            return Err(LauncherError::ProcessorFailed {
+                jar: processor.jar.clone(),
+                args: arguments,
+                exit_code: output.status.code(),
+                stdout,
+                stderr,
+            });
+        }
+
+        if let Some((path, expected, actual)) = check_outputs(&outputs).await? {
+            return Err(LauncherError::ProcessorError(format!(
+                "Processor {} produced {} with hash {} instead of {}",
+                processor.jar,
+                path.to_string_lossy(),
+                actual.as_deref().unwrap_or("none"),
+                expected
+            )));
+        }
+
+        if !state.completed.contains(&fingerprint) {
+            state.completed.push(fingerprint);
+            save_file(
+                &paths.state,
+                &bytes::Bytes::from(serde_json::to_string(&state)?),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn read_state(path: &Path) -> ProcessorState {
+    // A missing or unreadable state only means every processor without outputs runs again
+    std::fs::read(path)
+        .ok()
+        .and_then(|x| serde_json::from_slice(&x).ok())
+        .unwrap_or_default()
+}
+
+/// Identifies a processor of a side by its jar and unresolved arguments
+fn get_fingerprint(processor: &Processor, side: Side) -> String {
+    let mut key = format!("{}\n{}", side.as_str(), processor.jar);
+    for arg in &processor.args {
+        key.push('\n');
+        key.push_str(arg);
+    }
+
+    sha1::Sha1::from(key).hexdigest()
+}
+
+/// Resolves the files a processor outputs along with their expected SHA1 hashes
+fn get_outputs(
+    processor: &Processor,
+    paths: &ProcessorPaths,
+    data: &HashMap<String, SidedDataEntry>,
+    side: Side,
+) -> Result<Vec<(PathBuf, String)>, LauncherError> {
+    let mut outputs = Vec::new();
+
+    for (path, hash) in processor.outputs.iter().flatten() {
+        let resolve = |value: &str| {
+            args::get_processor_value(&paths.libraries, value, data, side)?.ok_or_else(|| {
+                LauncherError::ProcessorError(format!(
+                    "Unknown output {} of processor {}",
+                    value, processor.jar
+                ))
+            })
+        };
+
+        outputs.push((PathBuf::from(resolve(path)?), resolve(hash)?));
+    }
+
+    Ok(outputs)
+}
+
+/// Finds the first output which is missing or has an unexpected hash, returning its path, its
+/// expected hash and its actual hash if it exists
+async fn check_outputs(
+    outputs: &[(PathBuf, String)],
+) -> Result<Option<(PathBuf, String, Option<String>)>, LauncherError> {
+    for (path, expected) in outputs {
+        if !path.exists() {
+            return Ok(Some((path.clone(), expected.clone(), None)));
+        }
+
+        let actual = hash_file(path).await?.sha1;
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Ok(Some((path.clone(), expected.clone(), Some(actual))));
+        }
+    }
+
+    Ok(None)
+}
+
+fn unsided(value: &str) -> SidedDataEntry {
+    SidedDataEntry {
+        client: value.to_string(),
+        server: value.to_string(),
+    }
+}
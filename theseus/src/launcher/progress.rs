@@ -0,0 +1,104 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
+/// A stage of downloading a game version
+pub enum DownloadPhase {
+    /// The game client jar
+    Client,
+    /// The dedicated server jar
+    Server,
+    /// The objects in the version's assets index
+    Assets,
+    /// The library jars of the version
+    Libraries,
+    /// The native archives extracted for the current platform
+    Natives,
+}
+
+#[derive(Debug, Clone)]
+/// A snapshot of the progress of a download phase
+pub struct DownloadProgress {
+    /// The phase this snapshot belongs to
+    pub phase: DownloadPhase,
+    /// The amount of files which are present on the disk
+    pub completed_files: u64,
+    /// The amount of files the phase contains
+    pub total_files: u64,
+    /// The size of the files which are present on the disk
+    pub completed_bytes: u64,
+    /// The size of all files the phase contains. Files with an unknown size are not counted
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Sends download progress to a subscriber. The default reporter discards all progress
+pub struct ProgressReporter {
+    sender: Option<UnboundedSender<DownloadProgress>>,
+}
+
+impl ProgressReporter {
+    /// Creates a reporter along with the receiver its progress snapshots are sent to
+    pub fn new() -> (Self, UnboundedReceiver<DownloadProgress>) {
+        let (sender, receiver) = unbounded_channel();
+
+        (
+            ProgressReporter {
+                sender: Some(sender),
+            },
+            receiver,
+        )
+    }
+
+    pub(crate) fn phase(
+        &self,
+        phase: DownloadPhase,
+        total_files: u64,
+        total_bytes: u64,
+    ) -> PhaseProgress {
+        let progress = PhaseProgress {
+            reporter: self.clone(),
+            phase,
+            total_files,
+            total_bytes,
+            completed_files: AtomicU64::new(0),
+            completed_bytes: AtomicU64::new(0),
+        };
+
+        progress.report(0, 0);
+
+        progress
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct PhaseProgress {
+    reporter: ProgressReporter,
+    phase: DownloadPhase,
+    total_files: u64,
+    total_bytes: u64,
+    completed_files: AtomicU64,
+    completed_bytes: AtomicU64,
+}
+
+impl PhaseProgress {
+    pub fn complete_file(&self, bytes: u64) {
+        let completed_files = self.completed_files.fetch_add(1, Ordering::SeqCst) + 1;
+        let completed_bytes = self.completed_bytes.fetch_add(bytes, Ordering::SeqCst) + bytes;
+
+        self.report(completed_files, completed_bytes);
+    }
+
+    fn report(&self, completed_files: u64, completed_bytes: u64) {
+        if let Some(sender) = &self.reporter.sender {
+            // The subscriber may have stopped listening, which isn't an error for the download
+            let _ = sender.send(DownloadProgress {
+                phase: self.phase,
+                completed_files,
+                total_files: self.total_files,
+                completed_bytes,
+                total_bytes: self.total_bytes,
+            });
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use crate::launcher::LauncherError;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use std::future::Future;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+/// How failed downloads and metadata fetches are retried
+pub struct RetryPolicy {
+    /// How many times a request is attempted before giving up
+    pub max_attempts: u32,
+    /// How long to wait before the first retry. Every following retry waits twice as long
+    pub base_delay: Duration,
+    /// The longest time waited between two attempts, unless the server asks for longer
+    pub max_delay: Duration,
+    /// The longest time the server may ask to wait with `Retry-After`. Requests asking for
+    /// longer fail without another attempt
+    pub max_retry_after: Duration,
+    /// Whether delays are randomized, so many failed requests don't all retry at once
+    pub jitter: bool,
+    /// Response status codes which are worth retrying
+    pub retryable_statuses: Vec<StatusCode>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            max_retry_after: Duration::from_secs(120),
+            jitter: true,
+            retryable_statuses: vec![
+                StatusCode::REQUEST_TIMEOUT,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+        }
+    }
+}
+
+/// The error of a single attempt of a request
+#[derive(Debug)]
+pub(crate) enum AttemptError {
+    /// The attempt failed in a way another attempt may fix. The server may ask for a minimum
+    /// time to wait before the next attempt
+    Retry {
+        error: LauncherError,
+        after: Option<Duration>,
+    },
+    /// The attempt failed in a way retrying can't fix
+    Fatal(LauncherError),
+}
+
+impl From<LauncherError> for AttemptError {
+    fn from(err: LauncherError) -> Self {
+        AttemptError::Fatal(err)
+    }
+}
+
+impl From<std::io::Error> for AttemptError {
+    fn from(err: std::io::Error) -> Self {
+        AttemptError::Fatal(err.into())
+    }
+}
+
+impl RetryPolicy {
+    /// Runs a request until it succeeds, fails fatally or runs out of attempts. The request is
+    /// given the number of the current attempt, starting at 1
+    pub(crate) async fn run<T, F, Fut>(&self, url: &str, mut request: F) -> Result<T, LauncherError>
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = Result<T, AttemptError>>,
+    {
+        let max_attempts = self.max_attempts.max(1);
+        let mut errors = Vec::new();
+
+        for attempt in 1..=max_attempts {
+            match request(attempt).await {
+                Ok(value) => return Ok(value),
+                Err(AttemptError::Fatal(err)) => return Err(err),
+                Err(AttemptError::Retry { error, after }) => {
+                    errors.push(error);
+
+                    if attempt < max_attempts {
+                        tokio::time::sleep(after.unwrap_or_else(|| self.delay(attempt))).await;
+                    }
+                }
+            }
+        }
+
+        Err(LauncherError::RetriesExhausted {
+            url: url.to_string(),
+            errors,
+        })
+    }
+
+    /// Checks the status of a response. Statuses worth retrying become a retryable error, and
+    /// any other unsuccessful status a fatal one
+    pub(crate) fn check_response(
+        &self,
+        response: reqwest::Response,
+        url: &str,
+    ) -> Result<reqwest::Response, AttemptError> {
+        let status = response.status();
+
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        Err(self.status_error(status, response.headers(), url))
+    }
+
+    /// Turns an unsuccessful status into an attempt error. Statuses worth retrying are fatal
+    /// anyway when the server asks to wait longer than `max_retry_after`
+    fn status_error(&self, status: StatusCode, headers: &HeaderMap, url: &str) -> AttemptError {
+        let error = LauncherError::HttpError {
+            status,
+            url: url.to_string(),
+        };
+
+        if !self.retryable_statuses.contains(&status) {
+            return AttemptError::Fatal(error);
+        }
+
+        match get_retry_after(headers) {
+            Some(after) if after > self.max_retry_after => AttemptError::Fatal(error),
+            after => AttemptError::Retry { error, after },
+        }
+    }
+
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .checked_mul(2u32.saturating_pow(attempt - 1))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+
+        if self.jitter {
+            // Waits somewhere between half and all of the delay
+            delay / 2 + delay.mul_f64(rand::thread_rng().gen_range(0.0..0.5))
+        } else {
+            delay
+        }
+    }
+}
+
+/// Turns a network error into a retryable attempt error
+pub(crate) fn retry_fetch(err: reqwest::Error, url: &str) -> AttemptError {
+    AttemptError::Retry {
+        error: LauncherError::FetchError {
+            inner: err,
+            item: url.to_string(),
+        },
+        after: None,
+    }
+}
+
+/// Reads the `Retry-After` header, which is either a number of seconds or an HTTP date
+fn get_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+
+    date.with_timezone(&Utc)
+        .signed_duration_since(Utc::now())
+        .to_std()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderValue, RETRY_AFTER};
+
+    fn policy(jitter: bool) -> RetryPolicy {
+        RetryPolicy {
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            jitter,
+            ..RetryPolicy::default()
+        }
+    }
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        headers
+    }
+
+    #[test]
+    fn delay_doubles_up_to_the_maximum() {
+        let policy = policy(false);
+
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(4), Duration::from_secs(8));
+        assert_eq!(policy.delay(5), Duration::from_secs(10));
+        assert_eq!(policy.delay(100), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn delay_with_jitter_waits_at_least_half() {
+        let policy = policy(true);
+
+        for attempt in 1..=6 {
+            let delay = policy.delay(attempt);
+            let expected = policy.base_delay * 2u32.pow(attempt - 1);
+            let expected = expected.min(policy.max_delay);
+
+            assert!(delay >= expected / 2 && delay <= expected);
+        }
+    }
+
+    #[test]
+    fn retry_after_in_seconds() {
+        assert_eq!(
+            get_retry_after(&headers("120")),
+            Some(Duration::from_secs(120))
+        );
+    }
+
+    #[test]
+    fn retry_after_as_date() {
+        let date = (Utc::now() + chrono::Duration::seconds(60)).to_rfc2822();
+        let delay = get_retry_after(&headers(&date)).unwrap();
+
+        assert!(delay > Duration::from_secs(55) && delay <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn retry_after_in_the_past_or_invalid() {
+        let date = (Utc::now() - chrono::Duration::seconds(60)).to_rfc2822();
+
+        assert_eq!(get_retry_after(&headers(&date)), None);
+        assert_eq!(get_retry_after(&headers("soon")), None);
+        assert_eq!(get_retry_after(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn retry_after_up_to_the_maximum_is_obeyed() {
+        let policy = policy(false);
+        let error = policy.status_error(
+            StatusCode::TOO_MANY_REQUESTS,
+            &headers("120"),
+            "https://example.com",
+        );
+
+        assert!(matches!(
+            error,
+            AttemptError::Retry { after: Some(after), .. } if after == Duration::from_secs(120)
+        ));
+    }
+
+    #[test]
+    fn retry_after_over_the_maximum_is_fatal() {
+        let policy = policy(false);
+        let error = policy.status_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            &headers("3600"),
+            "https://example.com",
+        );
+
+        assert!(matches!(error, AttemptError::Fatal(_)));
+    }
+
+    #[test]
+    fn unlisted_status_is_fatal() {
+        let policy = policy(false);
+        let error = policy.status_error(
+            StatusCode::NOT_FOUND,
+            &HeaderMap::new(),
+            "https://example.com",
+        );
+
+        assert!(matches!(error, AttemptError::Fatal(_)));
+    }
+}
@@ -1,17 +1,87 @@
-use crate::launcher::download::get_os;
-use daedalus::minecraft::{OsRule, Rule, RuleAction};
+use crate::launcher::platform::{Arch, Platform};
+use daedalus::minecraft::{FeatureRule, OsRule, Rule, RuleAction};
+use lazy_static::lazy_static;
 use regex::Regex;
+use std::collections::HashMap;
+use std::sync::Mutex;
 
-pub fn parse_rules(rules: &[Rule]) -> bool {
-    rules.iter().all(|x| parse_rule(x))
+lazy_static! {
+    /// Compiled OS version patterns, which are shared by many libraries and arguments. Invalid
+    /// patterns are kept as `None`
+    static ref OS_VERSION_REGEXES: Mutex<HashMap<String, Option<Regex>>> =
+        Mutex::new(HashMap::new());
 }
 
-pub fn parse_rule(rule: &Rule) -> bool {
-    let result = if let Some(os) = &rule.os {
-        parse_os_rule(os)
-    } else {
-        rule.features.is_none()
-    };
+#[derive(Debug, Clone)]
+/// Everything rules of libraries and arguments are evaluated against: the platform the game runs
+/// on and the optional features of the launcher which are enabled
+pub struct RuleContext {
+    /// The platform the game runs on
+    pub platform: Platform,
+    /// Whether the game window is given a custom size
+    pub has_custom_resolution: bool,
+    /// Whether the game is started in demo mode
+    pub is_demo_user: bool,
+    /// Whether the launcher supports quick play, in which the game logs to a quick play file
+    pub has_quick_plays_support: bool,
+    /// Whether the game directly joins a singleplayer world
+    pub is_quick_play_singleplayer: bool,
+    /// Whether the game directly joins a multiplayer server
+    pub is_quick_play_multiplayer: bool,
+    /// Whether the game directly joins a Realm
+    pub is_quick_play_realms: bool,
+}
+
+impl Default for RuleContext {
+    fn default() -> Self {
+        RuleContext::host()
+    }
+}
+
+impl RuleContext {
+    /// A context for the platform the launcher is running on, with every feature disabled
+    pub fn host() -> Self {
+        RuleContext::new(Platform::host())
+    }
+
+    /// A context for the given platform, with every feature disabled
+    pub fn new(platform: Platform) -> Self {
+        RuleContext {
+            platform,
+            has_custom_resolution: false,
+            is_demo_user: false,
+            has_quick_plays_support: false,
+            is_quick_play_singleplayer: false,
+            is_quick_play_multiplayer: false,
+            is_quick_play_realms: false,
+        }
+    }
+}
+
+pub fn parse_rules(rules: &[Rule], context: &RuleContext) -> bool {
+    rules.iter().all(|x| parse_rule(x, context, &[]))
+}
+
+/// Evaluates the rules of an argument with the given values. The metadata only keeps the
+/// features daedalus knows about, so the features of a rule which lost them are inferred from
+/// the placeholders of its values
+pub fn parse_argument_rules(rules: &[Rule], values: &[String], context: &RuleContext) -> bool {
+    let implied = get_implied_features(values, context);
+
+    rules.iter().all(|x| parse_rule(x, context, &implied))
+}
+
+fn parse_rule(rule: &Rule, context: &RuleContext, implied_features: &[bool]) -> bool {
+    let result = rule
+        .os
+        .as_ref()
+        .map(|os| parse_os_rule(os, context))
+        .unwrap_or(true)
+        && rule
+            .features
+            .as_ref()
+            .map(|features| parse_feature_rule(features, context, implied_features))
+            .unwrap_or(true);
 
     match rule.action {
         RuleAction::Allow => result,
@@ -19,31 +89,24 @@ pub fn parse_rule(rule: &Rule) -> bool {
     }
 }
 
-pub fn parse_os_rule(rule: &OsRule) -> bool {
+pub fn parse_os_rule(rule: &OsRule, context: &RuleContext) -> bool {
     if let Some(arch) = &rule.arch {
-        match arch.as_str() {
-            "x86" => {
-                #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
-                return false;
-            }
-            "arm" => {
-                #[cfg(not(target_arch = "arm"))]
+        // Unknown architecture names don't restrict the rule
+        if let Some(arch) = Arch::from_name(arch) {
+            if arch != context.platform.arch {
                 return false;
             }
-            _ => {}
         }
     }
 
     if let Some(name) = &rule.name {
-        if &get_os() != name {
+        if &context.platform.os != name {
             return false;
         }
     }
     if let Some(version) = &rule.version {
-        let regex = Regex::new(version.as_str());
-
-        if let Ok(regex) = regex {
-            if !regex.is_match(&*sys_info::os_release().unwrap_or_default()) {
+        if let Some(regex) = get_os_version_regex(version) {
+            if !regex.is_match(&*context.platform.os_version) {
                 return false;
             }
         }
@@ -51,3 +114,178 @@ pub fn parse_os_rule(rule: &OsRule) -> bool {
 
     true
 }
+
+fn get_os_version_regex(pattern: &str) -> Option<Regex> {
+    // A poisoned cache only means another thread panicked while compiling, the entries are fine
+    let mut regexes = OS_VERSION_REGEXES
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+
+    regexes
+        .entry(pattern.to_string())
+        .or_insert_with(|| Regex::new(pattern).ok())
+        .clone()
+}
+
+fn parse_feature_rule(
+    rule: &FeatureRule,
+    context: &RuleContext,
+    implied_features: &[bool],
+) -> bool {
+    let known = [
+        (rule.is_demo_user, context.is_demo_user),
+        (rule.has_demo_resolution, context.has_custom_resolution),
+    ];
+
+    if known.iter().all(|(expected, _)| expected.is_none()) {
+        // A rule whose features are all unknown only matches if it implies some feature and
+        // all of them are enabled
+        return !implied_features.is_empty() && implied_features.iter().all(|x| *x);
+    }
+
+    known
+        .iter()
+        .all(|(expected, enabled)| expected.map(|x| x == *enabled).unwrap_or(true))
+}
+
+fn get_implied_features(values: &[String], context: &RuleContext) -> Vec<bool> {
+    let placeholders = [
+        ("${resolution_width}", context.has_custom_resolution),
+        ("${resolution_height}", context.has_custom_resolution),
+        ("${quickPlayPath}", context.has_quick_plays_support),
+        (
+            "${quickPlaySingleplayer}",
+            context.is_quick_play_singleplayer,
+        ),
+        ("${quickPlayMultiplayer}", context.is_quick_play_multiplayer),
+        ("${quickPlayRealms}", context.is_quick_play_realms),
+    ];
+
+    placeholders
+        .iter()
+        .filter(|(placeholder, _)| values.iter().any(|x| x.contains(placeholder)))
+        .map(|(_, enabled)| *enabled)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use daedalus::minecraft::Os;
+
+    fn context(os: Os, arch: Arch, os_version: &str) -> RuleContext {
+        RuleContext::new(Platform {
+            os,
+            arch,
+            os_version: os_version.to_string(),
+        })
+    }
+
+    fn os_rule(name: Option<Os>, version: Option<&str>, arch: Option<&str>) -> OsRule {
+        OsRule {
+            name,
+            version: version.map(|x| x.to_string()),
+            arch: arch.map(|x| x.to_string()),
+        }
+    }
+
+    fn feature_rule(features: FeatureRule) -> Rule {
+        Rule {
+            action: RuleAction::Allow,
+            os: None,
+            features: Some(features),
+        }
+    }
+
+    #[test]
+    fn os_rule_matches_arch() {
+        let context = context(Os::Linux, Arch::Arm64, "5.15.0");
+
+        assert!(parse_os_rule(&os_rule(None, None, Some("arm64")), &context));
+        assert!(parse_os_rule(
+            &os_rule(None, None, Some("aarch64")),
+            &context
+        ));
+        assert!(!parse_os_rule(&os_rule(None, None, Some("x86")), &context));
+        // Unknown architectures don't restrict the rule
+        assert!(parse_os_rule(
+            &os_rule(None, None, Some("riscv64")),
+            &context
+        ));
+    }
+
+    #[test]
+    fn os_rule_matches_name() {
+        let context = context(Os::Windows, Arch::X86_64, "10.0");
+
+        assert!(parse_os_rule(
+            &os_rule(Some(Os::Windows), None, None),
+            &context
+        ));
+        assert!(!parse_os_rule(
+            &os_rule(Some(Os::Osx), None, None),
+            &context
+        ));
+    }
+
+    #[test]
+    fn os_rule_matches_version() {
+        let context = context(Os::Osx, Arch::X86_64, "10.5.8");
+
+        assert!(parse_os_rule(
+            &os_rule(Some(Os::Osx), Some("^10\\.5\\.\\d$"), None),
+            &context
+        ));
+        assert!(!parse_os_rule(
+            &os_rule(Some(Os::Osx), Some("^10\\.6\\.\\d$"), None),
+            &context
+        ));
+        // Invalid patterns don't restrict the rule
+        assert!(parse_os_rule(
+            &os_rule(None, Some("^10\\.(5"), None),
+            &context
+        ));
+    }
+
+    #[test]
+    fn argument_rules_infer_features_from_values() {
+        let values = vec!["--width".to_string(), "${resolution_width}".to_string()];
+        let rules = [feature_rule(FeatureRule {
+            is_demo_user: None,
+            has_demo_resolution: None,
+        })];
+
+        let mut context = RuleContext::host();
+        assert!(!parse_argument_rules(&rules, &values, &context));
+
+        context.has_custom_resolution = true;
+        assert!(parse_argument_rules(&rules, &values, &context));
+    }
+
+    #[test]
+    fn argument_rules_without_implied_features_dont_match() {
+        let values = vec!["--unknownFeature".to_string()];
+        let rules = [feature_rule(FeatureRule {
+            is_demo_user: None,
+            has_demo_resolution: None,
+        })];
+
+        assert!(!parse_argument_rules(&rules, &values, &RuleContext::host()));
+    }
+
+    #[test]
+    fn argument_rules_prefer_known_features() {
+        let values = vec!["${quickPlayPath}".to_string()];
+        let rules = [feature_rule(FeatureRule {
+            is_demo_user: Some(true),
+            has_demo_resolution: None,
+        })];
+
+        let mut context = RuleContext::host();
+        context.has_quick_plays_support = true;
+        assert!(!parse_argument_rules(&rules, &values, &context));
+
+        context.is_demo_user = true;
+        assert!(parse_argument_rules(&rules, &values, &context));
+    }
+}